@@ -1,5 +1,6 @@
 use crate::{
-    game_state::DelayedState,
+    game_state::{DelayedState, GameStateStack},
+    schedule::{PhaseSystem, UpdatePhase},
     tween::{get_fade_out_sprite_anim, TweenDoneAction},
     GameState,
 };
@@ -19,7 +20,8 @@ pub struct AssetsPlugin;
 impl Plugin for AssetsPlugin {
     fn build(&self, app: &mut App) {
         app.add_startup_system(start_loading_assets).add_system_set(
-            SystemSet::on_update(GameState::Loading).with_system(check_assets_progress),
+            SystemSet::on_update(GameState::Loading)
+                .with_system(check_assets_progress.in_phase(UpdatePhase::Logic)),
         );
     }
 }
@@ -146,7 +148,7 @@ fn check_assets_progress(
                 loading_ass.done = true;
 
                 if cfg!(feature = "dev") {
-                    state.overwrite_set(GameState::Game).unwrap();
+                    state.go_to(GameState::Game).unwrap();
                 } else {
                     info!("queue state");
                     delayed_state.queue_state(GameState::Menu, 1.);
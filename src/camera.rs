@@ -0,0 +1,226 @@
+use crate::{
+    render::MainCamera,
+    replay::{get_scaled_delta, Replayer},
+    schedule::{PhaseSystem, UpdatePhase},
+    settings::Settings,
+};
+use bevy::prelude::*;
+use bevy_time::*;
+
+pub struct CameraPlugin;
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<CameraShakeEvt>()
+            .add_system(add_camera_shakes.in_phase(UpdatePhase::Logic))
+            .add_system(control_camera.in_phase(UpdatePhase::Render));
+    }
+}
+
+/// Entities the camera follows, with several targets it follows their weighted center
+#[derive(Component)]
+pub struct CameraTarget {
+    pub weight: f32,
+}
+
+impl Default for CameraTarget {
+    fn default() -> Self {
+        Self { weight: 1. }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CameraBounds {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+/// Shakes every controlled camera
+pub struct CameraShakeEvt {
+    /// Max offset in world units
+    pub amplitude: f32,
+    pub duration_sec: f32,
+}
+
+struct Shake {
+    amplitude: f32,
+    duration_sec: f32,
+    elapsed_sec: f32,
+}
+
+/// Drives the camera transform and zoom, this is the only place shake offsets are applied
+#[derive(Component)]
+pub struct CameraController {
+    /// Half size of the area around the focus the targets can move in without moving the camera
+    pub dead_zone: Vec2,
+    /// How far ahead of the targets to look, in seconds of their velocity
+    pub look_ahead_sec: f32,
+    /// Higher is snappier
+    pub smoothing: f32,
+    pub bounds: Option<CameraBounds>,
+    /// Projection scale, so values above 1 zoom out
+    pub target_zoom: f32,
+    pub zoom_smoothing: f32,
+    focus: Vec2,
+    zoom: f32,
+    look_ahead: Vec2,
+    prev_target_pos: Option<Vec2>,
+    shakes: Vec<Shake>,
+}
+
+impl Default for CameraController {
+    fn default() -> Self {
+        Self {
+            dead_zone: Vec2::new(40., 30.),
+            look_ahead_sec: 0.25,
+            smoothing: 6.,
+            bounds: None,
+            target_zoom: 1.,
+            zoom_smoothing: 8.,
+            focus: Vec2::ZERO,
+            zoom: 1.,
+            look_ahead: Vec2::ZERO,
+            prev_target_pos: None,
+            shakes: Vec::new(),
+        }
+    }
+}
+
+impl CameraController {
+    pub fn add_shake(&mut self, amplitude: f32, duration_sec: f32) {
+        self.shakes.push(Shake {
+            amplitude,
+            duration_sec,
+            elapsed_sec: 0.,
+        });
+    }
+
+    pub fn get_focus(&self) -> Vec2 {
+        self.focus
+    }
+
+    /// Moves the camera without smoothing, e.g. after a reset
+    pub fn snap_to(&mut self, pos: Vec2) {
+        self.focus = pos;
+        self.look_ahead = Vec2::ZERO;
+        self.prev_target_pos = None;
+    }
+
+    fn get_shake_offset(&mut self, dt: f32) -> Vec2 {
+        let mut offset = Vec2::ZERO;
+
+        for (i, shake) in self.shakes.iter_mut().enumerate() {
+            shake.elapsed_sec += dt;
+            let decay = 1. - (shake.elapsed_sec / shake.duration_sec).min(1.);
+            // out of phase sines per shake, so stacked shakes don't just add up
+            let t = shake.elapsed_sec * 40. + i as f32 * 1.7;
+            offset += Vec2::new(t.sin(), (t * 1.3).cos()) * shake.amplitude * decay * decay;
+        }
+
+        self.shakes.retain(|s| s.elapsed_sec < s.duration_sec);
+        offset
+    }
+}
+
+fn add_camera_shakes(
+    mut shake_evr: EventReader<CameraShakeEvt>,
+    mut controller_q: Query<&mut CameraController>,
+    settings: Res<Settings>,
+) {
+    for ev in shake_evr.iter() {
+        if settings.reduce_motion {
+            continue;
+        }
+
+        for mut controller in controller_q.iter_mut() {
+            controller.add_shake(ev.amplitude, ev.duration_sec);
+        }
+    }
+}
+
+fn control_camera(
+    mut camera_q: Query<
+        (
+            &mut CameraController,
+            &mut Transform,
+            &mut OrthographicProjection,
+        ),
+        With<MainCamera>,
+    >,
+    target_q: Query<(&GlobalTransform, &CameraTarget)>,
+    time: ScaledTime,
+    replayer: Option<Res<Replayer>>,
+) {
+    let dt = get_scaled_delta(&time, replayer.as_deref()).as_secs_f32();
+
+    if dt <= 0. {
+        return;
+    }
+
+    let (weighted_sum, weight_sum) =
+        target_q
+            .iter()
+            .fold((Vec2::ZERO, 0.), |(sum, weight_sum), (t, target)| {
+                (
+                    sum + t.translation.truncate() * target.weight,
+                    weight_sum + target.weight,
+                )
+            });
+    let target_pos = if weight_sum > 0. {
+        Some(weighted_sum / weight_sum)
+    } else {
+        None
+    };
+
+    // frame-rate independent lerp factor
+    let get_lerp = |smoothing: f32| 1. - (-smoothing * dt).exp();
+
+    for (mut controller, mut t, mut projection) in camera_q.iter_mut() {
+        let controller = &mut *controller;
+
+        if let Some(target_pos) = target_pos {
+            let velocity = controller
+                .prev_target_pos
+                .map_or(Vec2::ZERO, |prev| (target_pos - prev) / dt);
+            controller.prev_target_pos = Some(target_pos);
+            controller.look_ahead = controller.look_ahead.lerp(
+                velocity * controller.look_ahead_sec,
+                get_lerp(controller.smoothing * 0.5),
+            );
+
+            // only the part of the offset outside of the dead zone moves the camera
+            let offset = target_pos + controller.look_ahead - controller.focus;
+            let excess = offset.signum() * (offset.abs() - controller.dead_zone).max(Vec2::ZERO);
+            controller.focus += excess * get_lerp(controller.smoothing);
+        } else {
+            controller.prev_target_pos = None;
+        }
+
+        controller.zoom +=
+            (controller.target_zoom - controller.zoom) * get_lerp(controller.zoom_smoothing);
+        // avoid flagging the projection as changed every frame
+        if projection.scale != controller.zoom {
+            projection.scale = controller.zoom;
+        }
+
+        if let Some(bounds) = controller.bounds {
+            let half_view = Vec2::new(
+                projection.right - projection.left,
+                projection.top - projection.bottom,
+            ) * projection.scale
+                * 0.5;
+            let min = bounds.min + half_view;
+            let max = bounds.max - half_view;
+
+            // center when the view is bigger than the bounds
+            controller.focus = Vec2::select(
+                min.cmpgt(max),
+                (bounds.min + bounds.max) * 0.5,
+                controller.focus.clamp(min.min(max), max.max(min)),
+            );
+        }
+
+        let shake_offset = controller.get_shake_offset(dt);
+        let pos = controller.focus + shake_offset;
+        t.translation = pos.extend(t.translation.z);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::schedule::{PhaseSystem, UpdatePhase};
 use bevy::prelude::*;
 #[allow(unused_imports)]
 use bevy_inspector_egui::{InspectorPlugin, RegisterInspectable, WorldInspectorPlugin};
@@ -13,7 +14,7 @@ impl Plugin for DebugPlugin {
             // inspectable example
             // .register_inspectable::<>()
             .add_startup_system(test_setup)
-            .add_system(test_system);
+            .add_system(test_system.in_phase(UpdatePhase::Logic));
     }
 }
 
@@ -0,0 +1,569 @@
+// dialogue scripts with a typewriter textbox, configured in `assets/dialogues/*.dialogue.ron`
+
+use crate::{
+    assets::Fonts,
+    inactive::{InactiveCommands, InactiveReason},
+    input_binding::{get_menu_input_map, UiAction, UiInput},
+    menu::{spawn_choice_buttons, ButtonInteractionStyles, UiFocus},
+    palette::{Palette, PaletteColor},
+    reset::Persistent,
+    schedule::{PhaseSystem, UpdatePhase},
+    settings::Settings,
+    sfx::QueueSoundEvt,
+};
+use bevy::{
+    asset::{AssetLoader, BoxedFuture, LoadContext, LoadState, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    ui::FocusPolicy,
+};
+use bevy_time::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+pub struct DialoguePlugin;
+impl Plugin for DialoguePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Dialogue>()
+            .add_asset::<DialogueScript>()
+            .init_asset_loader::<DialogueScriptLoader>()
+            .add_event::<StartDialogue>()
+            .add_event::<DialogueChoiceEvt>()
+            .add_event::<DialogueEndedEvt>()
+            .add_system(start_dialogue.in_phase(UpdatePhase::Logic))
+            .add_system(
+                update_dialogue
+                    .in_phase(UpdatePhase::Logic)
+                    .after(start_dialogue),
+            )
+            .add_system(
+                spawn_dialogue_choices
+                    .in_phase(UpdatePhase::Logic)
+                    .after(update_dialogue),
+            );
+    }
+}
+
+pub const TUTORIAL_DIALOGUE_PATH: &str = "dialogues/tutorial.dialogue.ron";
+
+const CHARS_PER_SEC: f32 = 40.;
+/// A blip plays every n revealed characters
+const CHARS_PER_BLIP: usize = 2;
+const BLIP_VOLUME: f32 = 0.15;
+/// Blips can reuse e.g. the button click sounds, but shouldn't change their volume
+const BLIP_CHANNEL_SUFFIX: usize = 1;
+const TEXT_WIDTH: f32 = 800.;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SpeakerBlip {
+    /// Sfx file prefix, the variants are picked at random
+    pub prefix: String,
+    #[serde(default = "get_default_blip_variants")]
+    pub variants: u8,
+}
+
+fn get_default_blip_variants() -> u8 {
+    1
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Speaker {
+    pub name: String,
+    #[serde(default)]
+    pub portrait: Option<String>,
+    #[serde(default)]
+    pub blip: Option<SpeakerBlip>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DialogueLine {
+    pub speaker: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DialogueChoice {
+    pub text: String,
+    /// Ends the dialogue when `None`
+    #[serde(default)]
+    pub next: Option<String>,
+}
+
+/// The choices are offered once all lines have been shown,
+/// without any the dialogue continues with the next node or ends
+#[derive(Debug, Clone, Deserialize)]
+pub struct DialogueNode {
+    pub lines: Vec<DialogueLine>,
+    #[serde(default)]
+    pub choices: Vec<DialogueChoice>,
+    #[serde(default)]
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, TypeUuid)]
+#[uuid = "5b0f3c1e-8d57-4d6a-9a2e-6f1c2b7d9e41"]
+pub struct DialogueScript {
+    pub speakers: HashMap<String, Speaker>,
+    pub start: String,
+    pub nodes: HashMap<String, DialogueNode>,
+}
+
+impl DialogueScript {
+    fn get_line(&self, node: &str, line_index: usize) -> Option<&DialogueLine> {
+        self.nodes.get(node)?.lines.get(line_index)
+    }
+}
+
+#[derive(Default)]
+struct DialogueScriptLoader;
+
+impl AssetLoader for DialogueScriptLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let script: DialogueScript = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(script));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["dialogue.ron"]
+    }
+}
+
+/// Ignored while another dialogue is running
+pub struct StartDialogue {
+    /// Asset path of the script
+    pub path: String,
+    /// Starts at the script's start node when `None`
+    pub node: Option<String>,
+}
+
+impl StartDialogue {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            node: None,
+        }
+    }
+}
+
+/// Index of the picked choice of the current node
+pub struct DialogueChoiceEvt(pub usize);
+
+pub struct DialogueEndedEvt {
+    pub path: String,
+}
+
+#[derive(Default)]
+pub struct Dialogue {
+    active: Option<ActiveDialogue>,
+}
+
+impl Dialogue {
+    pub fn is_active(&self) -> bool {
+        self.active.is_some()
+    }
+}
+
+struct ActiveDialogue {
+    path: String,
+    script: Handle<DialogueScript>,
+    node: Option<String>,
+    line_index: usize,
+    step: DialogueStep,
+    box_e: Entity,
+    content_e: Entity,
+    /// Panels which got disabled for the duration of the dialogue
+    paused_panels: Vec<Entity>,
+}
+
+#[derive(PartialEq)]
+enum DialogueStep {
+    Loading,
+    Typing {
+        revealed: f32,
+    },
+    Waiting,
+    /// The panel is spawned by a separate system
+    Choosing {
+        panel_e: Option<Entity>,
+    },
+}
+
+#[derive(Component)]
+struct DialogueBox;
+
+#[derive(Component)]
+enum DialoguePart {
+    Name,
+    Text,
+    Portrait,
+}
+
+fn start_dialogue(
+    mut commands: Commands,
+    mut start_evr: EventReader<StartDialogue>,
+    mut dialogue: ResMut<Dialogue>,
+    panel_q: Query<Entity, With<UiFocus>>,
+    ass: Res<AssetServer>,
+    fonts: Res<Fonts>,
+    palette: Res<Palette>,
+    settings: Res<Settings>,
+) {
+    for ev in start_evr.iter() {
+        if dialogue.is_active() {
+            continue;
+        }
+
+        // menus underneath would react to the confirm presses as well
+        let paused_panels: Vec<_> = panel_q.iter().collect();
+        for panel_e in paused_panels.iter() {
+            commands.deactivate(*panel_e, InactiveReason::Cutscene);
+        }
+
+        let (box_e, content_e) = spawn_dialogue_box(&mut commands, &fonts, &palette, &settings);
+
+        dialogue.active = Some(ActiveDialogue {
+            path: ev.path.clone(),
+            script: ass.load(ev.path.as_str()),
+            node: ev.node.clone(),
+            line_index: 0,
+            step: DialogueStep::Loading,
+            box_e,
+            content_e,
+            paused_panels,
+        });
+    }
+}
+
+fn spawn_dialogue_box(
+    commands: &mut Commands,
+    fonts: &Fonts,
+    palette: &Palette,
+    settings: &Settings,
+) -> (Entity, Entity) {
+    let get_text_bundle = |font_size: f32| TextBundle {
+        style: Style {
+            max_size: Size::new(Val::Px(TEXT_WIDTH), Val::Undefined),
+            margin: Rect {
+                bottom: Val::Px(10.),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        text: Text {
+            sections: vec![
+                TextSection {
+                    value: String::new(),
+                    style: TextStyle {
+                        font: fonts.ui.clone(),
+                        font_size,
+                        color: palette.get_color(&PaletteColor::Text),
+                    },
+                },
+                // the unrevealed rest, so the text doesn't reflow while typing
+                TextSection {
+                    value: String::new(),
+                    style: TextStyle {
+                        font: fonts.ui.clone(),
+                        font_size,
+                        color: Color::NONE,
+                    },
+                },
+            ],
+            alignment: Default::default(),
+        },
+        focus_policy: FocusPolicy::Pass,
+        ..Default::default()
+    };
+    let mut content_e = None;
+
+    let box_e = commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(30.)),
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    bottom: Val::Px(0.),
+                    left: Val::Px(0.),
+                    ..Default::default()
+                },
+                padding: Rect::all(Val::Px(20.)),
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            color: palette.get_color(&PaletteColor::Background).into(),
+            ..Default::default()
+        })
+        .insert(PaletteColor::Background)
+        .insert(DialogueBox)
+        // the dialogue despawns the box itself once it's over
+        .insert(Persistent)
+        .insert(get_menu_input_map(&settings.bindings).unwrap())
+        .with_children(|b| {
+            b.spawn_bundle(ImageBundle {
+                style: Style {
+                    size: Size::new(Val::Px(150.), Val::Px(150.)),
+                    margin: Rect {
+                        right: Val::Px(20.),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                visibility: Visibility { is_visible: false },
+                ..Default::default()
+            })
+            .insert(DialoguePart::Portrait);
+
+            content_e = Some(
+                b.spawn_bundle(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::ColumnReverse,
+                        flex_grow: 1.,
+                        ..Default::default()
+                    },
+                    color: Color::NONE.into(),
+                    ..Default::default()
+                })
+                .with_children(|b| {
+                    b.spawn_bundle(get_text_bundle(30.))
+                        .insert(PaletteColor::Text)
+                        .insert(DialoguePart::Name);
+                    b.spawn_bundle(get_text_bundle(25.))
+                        .insert(PaletteColor::Text)
+                        .insert(DialoguePart::Text);
+                })
+                .id(),
+            );
+        })
+        .id();
+
+    (box_e, content_e.unwrap())
+}
+
+fn update_dialogue(
+    mut commands: Commands,
+    mut dialogue: ResMut<Dialogue>,
+    mut choice_evr: EventReader<DialogueChoiceEvt>,
+    mut ended_evw: EventWriter<DialogueEndedEvt>,
+    mut sfx_evw: EventWriter<QueueSoundEvt>,
+    scripts: Res<Assets<DialogueScript>>,
+    box_q: Query<&UiInput, With<DialogueBox>>,
+    mut part_q: Query<(
+        &DialoguePart,
+        Option<&mut Text>,
+        Option<&mut UiImage>,
+        &mut Visibility,
+    )>,
+    ass: Res<AssetServer>,
+    time: ScaledTime,
+) {
+    let active = match dialogue.active.as_mut() {
+        Some(active) => active,
+        None => return,
+    };
+    let script = match scripts.get(&active.script) {
+        Some(script) => script,
+        None => {
+            if ass.get_load_state(&active.script) == LoadState::Failed {
+                warn!("Dialogue script {} failed to load", active.path);
+                end_dialogue(&mut commands, &mut dialogue, &mut ended_evw);
+            }
+
+            return;
+        }
+    };
+
+    if active.step == DialogueStep::Loading {
+        active.node.get_or_insert_with(|| script.start.clone());
+        active.step = DialogueStep::Typing { revealed: 0. };
+    }
+
+    let node_id = active.node.clone().unwrap_or_default();
+    let is_confirmed = box_q
+        .get(active.box_e)
+        .map_or(false, |input| input.just_pressed(UiAction::Confirm));
+
+    match &mut active.step {
+        DialogueStep::Typing { revealed } => {
+            let line_len = script
+                .get_line(&node_id, active.line_index)
+                .map_or(0, |l| l.text.chars().count());
+            let prev_revealed = *revealed as usize;
+
+            // real time, so dialogues work in a paused game
+            *revealed += time.delta().as_secs_f32() * CHARS_PER_SEC;
+
+            if is_confirmed || *revealed as usize >= line_len {
+                // skipping doesn't blip
+                active.step = DialogueStep::Waiting;
+            } else if prev_revealed / CHARS_PER_BLIP != *revealed as usize / CHARS_PER_BLIP {
+                if let Some(blip) = script
+                    .get_line(&node_id, active.line_index)
+                    .and_then(|l| script.speakers.get(&l.speaker))
+                    .and_then(|s| s.blip.as_ref())
+                {
+                    sfx_evw.send(
+                        QueueSoundEvt::new(&blip.prefix, 1..=blip.variants.max(1), BLIP_VOLUME)
+                            .with_channel_suffix(BLIP_CHANNEL_SUFFIX),
+                    );
+                }
+            }
+        }
+        DialogueStep::Waiting => {
+            if is_confirmed {
+                let node = script.nodes.get(&node_id);
+                let line_count = node.map_or(0, |n| n.lines.len());
+
+                if active.line_index + 1 < line_count {
+                    active.line_index += 1;
+                    active.step = DialogueStep::Typing { revealed: 0. };
+                } else if node.map_or(false, |n| !n.choices.is_empty()) {
+                    active.step = DialogueStep::Choosing { panel_e: None };
+                } else {
+                    let next = node.and_then(|n| n.next.clone());
+                    go_to_node(&mut commands, &mut dialogue, &mut ended_evw, next);
+                    return;
+                }
+            }
+        }
+        DialogueStep::Choosing { panel_e } => {
+            if let Some(DialogueChoiceEvt(index)) = choice_evr.iter().last() {
+                if let Some(panel_e) = panel_e {
+                    commands.entity(*panel_e).despawn_recursive();
+                }
+
+                let next = script
+                    .nodes
+                    .get(&node_id)
+                    .and_then(|n| n.choices.get(*index))
+                    .and_then(|c| c.next.clone());
+                go_to_node(&mut commands, &mut dialogue, &mut ended_evw, next);
+                return;
+            }
+        }
+        DialogueStep::Loading => {}
+    }
+
+    let line = match script.get_line(&node_id, active.line_index) {
+        Some(line) => line,
+        None => {
+            warn!("Dialogue node {node_id} in {} has no lines", active.path);
+            end_dialogue(&mut commands, &mut dialogue, &mut ended_evw);
+            return;
+        }
+    };
+    let speaker = script.speakers.get(&line.speaker);
+    let revealed = match active.step {
+        DialogueStep::Typing { revealed } => revealed as usize,
+        _ => usize::MAX,
+    };
+    // byte index of the first hidden char
+    let split = line
+        .text
+        .char_indices()
+        .nth(revealed)
+        .map_or(line.text.len(), |(i, _)| i);
+
+    for (part, text, img, mut visibility) in part_q.iter_mut() {
+        match (part, text, img) {
+            (DialoguePart::Name, Some(mut text), _) => {
+                let name = speaker.map_or(line.speaker.as_str(), |s| s.name.as_str());
+                if text.sections[0].value != name {
+                    text.sections[0].value = name.to_string();
+                }
+            }
+            (DialoguePart::Text, Some(mut text), _) => {
+                if text.sections[0].value != line.text[..split] {
+                    text.sections[0].value = line.text[..split].to_string();
+                    text.sections[1].value = line.text[split..].to_string();
+                }
+            }
+            (DialoguePart::Portrait, _, Some(mut img)) => {
+                let portrait = speaker.and_then(|s| s.portrait.as_ref());
+                visibility.is_visible = portrait.is_some();
+
+                if let Some(portrait) = portrait {
+                    let handle: Handle<Image> = ass.load(portrait.as_str());
+                    if img.0 != handle {
+                        img.0 = handle;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn go_to_node(
+    commands: &mut Commands,
+    dialogue: &mut Dialogue,
+    ended_evw: &mut EventWriter<DialogueEndedEvt>,
+    node: Option<String>,
+) {
+    match (node, dialogue.active.as_mut()) {
+        (Some(node), Some(active)) => {
+            active.node = Some(node);
+            active.line_index = 0;
+            active.step = DialogueStep::Typing { revealed: 0. };
+        }
+        _ => end_dialogue(commands, dialogue, ended_evw),
+    }
+}
+
+fn end_dialogue(
+    commands: &mut Commands,
+    dialogue: &mut Dialogue,
+    ended_evw: &mut EventWriter<DialogueEndedEvt>,
+) {
+    if let Some(active) = dialogue.active.take() {
+        commands.entity(active.box_e).despawn_recursive();
+
+        for panel_e in active.paused_panels {
+            commands.activate(panel_e, InactiveReason::Cutscene);
+        }
+
+        ended_evw.send(DialogueEndedEvt { path: active.path });
+    }
+}
+
+fn spawn_dialogue_choices(
+    mut commands: Commands,
+    mut dialogue: ResMut<Dialogue>,
+    scripts: Res<Assets<DialogueScript>>,
+    fonts: Res<Fonts>,
+    palette: Res<Palette>,
+    btn_style: Res<ButtonInteractionStyles>,
+    settings: Res<Settings>,
+) {
+    let active = match dialogue.active.as_mut() {
+        Some(active) => active,
+        None => return,
+    };
+
+    if let DialogueStep::Choosing { panel_e: None } = active.step {
+        let choices: Vec<&str> = active
+            .node
+            .as_ref()
+            .and_then(|node| scripts.get(&active.script)?.nodes.get(node))
+            .map(|node| node.choices.iter().map(|c| c.text.as_str()).collect())
+            .unwrap_or_default();
+
+        active.step = DialogueStep::Choosing {
+            panel_e: Some(spawn_choice_buttons(
+                active.content_e,
+                &choices,
+                &mut commands,
+                &palette,
+                &fonts,
+                &btn_style,
+                &settings,
+            )),
+        };
+    }
+}
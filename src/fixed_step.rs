@@ -0,0 +1,274 @@
+// fixed timestep for gameplay, rendering interpolates the transforms between ticks.
+// Heron physics isn't part of it, it steps in its own stage with its own accumulator.
+
+use crate::{
+    replay::{get_scaled_delta, Replayer},
+    schedule::{PhaseSystem, UpdatePhase},
+};
+use bevy::{
+    ecs::schedule::{ParallelSystemDescriptor, ShouldRun},
+    prelude::*,
+    transform::TransformSystem,
+};
+use bevy_time::*;
+use heron::{PhysicsSteps, PhysicsTime};
+
+pub struct FixedStepPlugin;
+impl Plugin for FixedStepPlugin {
+    fn build(&self, app: &mut App) {
+        // a step inserted before adding the plugin keeps its rate
+        app.init_resource::<FixedStep>()
+            .add_stage_after(
+                CoreStage::Update,
+                FixedUpdateStage,
+                SystemStage::parallel().with_run_criteria(run_fixed_step),
+            )
+            .add_system_to_stage(
+                FixedUpdateStage,
+                restore_fixed_transforms.label(FixedStepSystem::RestoreTransforms),
+            )
+            .add_system_to_stage(
+                FixedUpdateStage,
+                store_fixed_transforms.label(FixedStepSystem::StoreTransforms),
+            )
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                interpolate_transforms.before(TransformSystem::TransformPropagate),
+            )
+            .add_system(sync_physics_step.in_phase(UpdatePhase::Physics));
+    }
+}
+
+pub const DEFAULT_TICK_RATE: u32 = 60;
+pub const DEFAULT_MAX_CATCH_UP_STEPS: u32 = 5;
+
+/// Runs after [`CoreStage::Update`] as many times per frame as there are whole ticks in the scaled delta,
+/// so it doesn't run at all while the game is paused
+#[derive(Debug, Clone, PartialEq, Eq, Hash, StageLabel)]
+pub struct FixedUpdateStage;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub enum FixedStepSystem {
+    RestoreTransforms,
+    StoreTransforms,
+}
+
+pub trait FixedSystem<Params> {
+    /// Orders the system between restoring and storing the simulated transforms
+    fn in_fixed_step(self) -> ParallelSystemDescriptor;
+}
+
+impl<S, Params> FixedSystem<Params> for S
+where
+    S: ParallelSystemDescriptorCoercion<Params>,
+{
+    fn in_fixed_step(self) -> ParallelSystemDescriptor {
+        self.after(FixedStepSystem::RestoreTransforms)
+            .before(FixedStepSystem::StoreTransforms)
+    }
+}
+
+pub struct FixedStep {
+    /// Ticks per second
+    pub tick_rate: u32,
+    /// Ticks run in a single frame at most, the rest of the backlog is dropped.
+    /// Keeps a slow frame from causing even slower frames.
+    pub max_catch_up_steps: u32,
+    accumulator: f32,
+    steps_this_frame: u32,
+    is_frame_started: bool,
+    alpha: f32,
+    tick: u64,
+}
+
+impl Default for FixedStep {
+    fn default() -> Self {
+        Self::new(DEFAULT_TICK_RATE, DEFAULT_MAX_CATCH_UP_STEPS)
+    }
+}
+
+impl FixedStep {
+    pub fn new(tick_rate: u32, max_catch_up_steps: u32) -> Self {
+        Self {
+            tick_rate,
+            max_catch_up_steps,
+            accumulator: 0.,
+            steps_this_frame: 0,
+            is_frame_started: false,
+            alpha: 0.,
+            tick: 0,
+        }
+    }
+
+    /// Delta of a single tick, use it instead of the frame delta in fixed systems
+    pub fn get_step_sec(&self) -> f32 {
+        1. / self.tick_rate.max(1) as f32
+    }
+
+    /// How far between the last two ticks the rendered frame is, in the 0..1 range
+    pub fn get_alpha(&self) -> f32 {
+        self.alpha
+    }
+
+    /// Ticks run since startup
+    pub fn get_tick(&self) -> u64 {
+        self.tick
+    }
+
+    fn start_frame(&mut self, delta_sec: f32) {
+        self.is_frame_started = true;
+        self.steps_this_frame = 0;
+        self.accumulator += delta_sec;
+    }
+
+    /// Consumes a tick from the accumulator, ends the frame when there's none left
+    fn try_step(&mut self) -> bool {
+        let step = self.get_step_sec();
+
+        if self.accumulator >= step && self.steps_this_frame < self.max_catch_up_steps {
+            self.accumulator -= step;
+            self.steps_this_frame += 1;
+            self.tick += 1;
+            return true;
+        }
+
+        if self.accumulator >= step {
+            self.accumulator %= step;
+        }
+
+        self.alpha = self.accumulator / step;
+        self.is_frame_started = false;
+        false
+    }
+}
+
+/// Transform driven by fixed systems, rendered interpolated between the last two ticks.
+/// Teleports from outside of the fixed step have to go through [`Interpolated::snap`],
+/// otherwise the next tick restores the simulated transform.
+#[derive(Component, Default)]
+pub struct Interpolated {
+    prev: Option<Transform>,
+    current: Option<Transform>,
+}
+
+impl Interpolated {
+    pub fn snap(&mut self, transform: Transform) {
+        self.prev = Some(transform);
+        self.current = Some(transform);
+    }
+}
+
+fn run_fixed_step(
+    mut fixed: ResMut<FixedStep>,
+    time: ScaledTime,
+    replayer: Option<Res<Replayer>>,
+) -> ShouldRun {
+    if !fixed.is_frame_started {
+        // replays tick with the recorded deltas, so they run the same number of ticks
+        let delta = get_scaled_delta(&time, replayer.as_deref());
+        fixed.start_frame(delta.as_secs_f32());
+    }
+
+    if fixed.try_step() {
+        ShouldRun::YesAndCheckAgain
+    } else {
+        ShouldRun::No
+    }
+}
+
+/// Heron steps in its own stage, so it only shares the tick rate and the time scale.
+/// Its ticks aren't aligned with the fixed step ones, the catch-up cap doesn't apply to it
+/// and replays don't drive it with the recorded deltas.
+fn sync_physics_step(
+    mut commands: Commands,
+    fixed: Res<FixedStep>,
+    time_scale: Res<TimeScale>,
+    mut physics_time: ResMut<PhysicsTime>,
+    mut tick_rate: Local<Option<u32>>,
+) {
+    if *tick_rate != Some(fixed.tick_rate) {
+        *tick_rate = Some(fixed.tick_rate);
+        commands.insert_resource(PhysicsSteps::from_steps_per_seconds(
+            fixed.tick_rate.max(1) as f32
+        ));
+    }
+
+    if time_scale.is_changed() {
+        physics_time.set_scale(time_scale.0);
+    }
+}
+
+fn restore_fixed_transforms(mut q: Query<(&Interpolated, &mut Transform)>) {
+    for (interpolated, mut t) in q.iter_mut() {
+        if let Some(current) = interpolated.current {
+            *t = current;
+        }
+    }
+}
+
+fn store_fixed_transforms(mut q: Query<(&mut Interpolated, &Transform)>) {
+    for (mut interpolated, t) in q.iter_mut() {
+        interpolated.prev = interpolated.current.or(Some(*t));
+        interpolated.current = Some(*t);
+    }
+}
+
+fn interpolate_transforms(mut q: Query<(&Interpolated, &mut Transform)>, fixed: Res<FixedStep>) {
+    let alpha = fixed.get_alpha();
+
+    for (interpolated, mut t) in q.iter_mut() {
+        if let (Some(prev), Some(current)) = (interpolated.prev, interpolated.current) {
+            t.translation = prev.translation.lerp(current.translation, alpha);
+            t.rotation = prev.rotation.slerp(current.rotation, alpha);
+            t.scale = prev.scale.lerp(current.scale, alpha);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_steps(fixed: &mut FixedStep, delta_sec: f32) -> u32 {
+        fixed.start_frame(delta_sec);
+        let mut steps = 0;
+
+        while fixed.try_step() {
+            steps += 1;
+        }
+
+        steps
+    }
+
+    #[test]
+    fn steps_accumulate() {
+        let mut fixed = FixedStep::new(10, 5);
+
+        assert_eq!(get_steps(&mut fixed, 0.05), 0);
+        assert!((fixed.get_alpha() - 0.5).abs() < 0.001);
+        assert_eq!(get_steps(&mut fixed, 0.075), 1);
+        assert!((fixed.get_alpha() - 0.25).abs() < 0.001);
+        assert_eq!(fixed.get_tick(), 1);
+    }
+
+    #[test]
+    fn catch_up_is_capped() {
+        let mut fixed = FixedStep::new(10, 3);
+
+        assert_eq!(get_steps(&mut fixed, 1.05), 3);
+        // the backlog is dropped, so the next frame doesn't catch up either
+        assert_eq!(get_steps(&mut fixed, 0.), 0);
+        assert!((fixed.get_alpha() - 0.5).abs() < 0.001);
+    }
+
+    #[test]
+    fn paused_time_does_not_step() {
+        let mut fixed = FixedStep::default();
+
+        for _ in 0..10 {
+            assert_eq!(get_steps(&mut fixed, 0.), 0);
+        }
+
+        assert_eq!(fixed.get_tick(), 0);
+    }
+}
@@ -1,71 +1,588 @@
+use crate::{
+    replay::{get_scaled_delta, Replayer},
+    schedule::{PhaseSystem, UpdatePhase},
+};
 use bevy::{ecs::schedule::StateData, prelude::*};
 use bevy_time::*;
+use std::{collections::VecDeque, time::Duration};
 
 pub struct GameStatePlugin;
 impl Plugin for GameStatePlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<DelayedState<GameState>>()
-            .add_system(set_delayed_state::<GameState>);
+            .init_resource::<StateHistory>()
+            .add_event::<GameStateHook>()
+            .add_event::<StateTransitionQueued<GameState>>()
+            .add_event::<StateTransitionApplied<GameState>>()
+            .add_system(set_delayed_state::<GameState>.in_phase(UpdatePhase::Logic))
+            .add_system_to_stage(CoreStage::PostUpdate, record_state_history);
     }
 }
 
+/// Only the last entries are kept
+const STATE_HISTORY_LEN: usize = 32;
+
+/// Base states replace the whole stack, overlays are pushed on top of it.
+/// Change states through [`GameStateStack`] instead of `State` directly, so the stack can't grow.
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 pub enum GameState {
     Loading,
     Menu,
+    Lobby,
     Game,
+    /// Overlay, opened on top of `Game` when a player's gamepad disconnects
+    Disconnected,
+    /// Overlay, opened on top of `Game` by the pause menu
+    Paused,
+    /// Leaves `Game` so it gets set up from scratch once the reset is done
     Reset,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash, SystemLabel)]
-pub enum UpdatePhase {
-    Input,
-    Physics,
-    Movement,
-    Animation,
-    Audio,
-    Render,
+impl GameState {
+    /// The states below an overlay keep their entities, but their `on_update` systems don't run
+    pub fn is_overlay(&self) -> bool {
+        matches!(self, GameState::Disconnected | GameState::Paused)
+    }
 }
 
-pub struct DelayedState<T: StateData> {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameStateError {
+    /// Overlays can only be opened and closed
+    IsOverlay(GameState),
+    /// Base states can only be gone to
+    NotOverlay(GameState),
+    AlreadyInState(GameState),
+    AlreadyOpen(GameState),
+    /// Only the topmost overlay can be closed
+    NotOnTop(GameState),
+}
+
+pub trait GameStateStack {
+    /// Replaces the whole stack (including open overlays) with the base state
+    fn go_to(&mut self, state: GameState) -> Result<(), GameStateError>;
+    fn open(&mut self, overlay: GameState) -> Result<(), GameStateError>;
+    fn close(&mut self, overlay: GameState) -> Result<(), GameStateError>;
+    /// Bottom to top
+    fn get_stack(&self) -> Vec<GameState>;
+}
+
+impl GameStateStack for State<GameState> {
+    fn go_to(&mut self, state: GameState) -> Result<(), GameStateError> {
+        if state.is_overlay() {
+            return Err(GameStateError::IsOverlay(state));
+        }
+
+        if *self.current() == state {
+            return Err(GameStateError::AlreadyInState(state));
+        }
+
+        self.overwrite_replace(state)
+            .map_err(|_| GameStateError::AlreadyInState(state))
+    }
+
+    fn open(&mut self, overlay: GameState) -> Result<(), GameStateError> {
+        if !overlay.is_overlay() {
+            return Err(GameStateError::NotOverlay(overlay));
+        }
+
+        if self.get_stack().contains(&overlay) {
+            return Err(GameStateError::AlreadyOpen(overlay));
+        }
+
+        self.overwrite_push(overlay)
+            .map_err(|_| GameStateError::AlreadyOpen(overlay))
+    }
+
+    fn close(&mut self, overlay: GameState) -> Result<(), GameStateError> {
+        if !overlay.is_overlay() {
+            return Err(GameStateError::NotOverlay(overlay));
+        }
+
+        if *self.current() != overlay {
+            return Err(GameStateError::NotOnTop(overlay));
+        }
+
+        self.overwrite_pop()
+            .map_err(|_| GameStateError::NotOnTop(overlay))
+    }
+
+    fn get_stack(&self) -> Vec<GameState> {
+        let mut stack = self.inactives().to_vec();
+        stack.push(*self.current());
+        stack
+    }
+}
+
+/// Sent after the state stack has changed, in the order the changes happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameStateHook {
+    Enter(GameState),
+    Exit(GameState),
+    /// An overlay was opened on top of the state
+    Pause(GameState),
+    /// The overlay on top of the state was closed
+    Resume(GameState),
+}
+
+#[derive(Debug, Clone)]
+pub struct StateHistoryEntry {
+    pub stack: Vec<GameState>,
+    pub elapsed_sec: f64,
+}
+
+/// Recent state stacks for debugging, oldest first
+#[derive(Default)]
+pub struct StateHistory {
+    entries: VecDeque<StateHistoryEntry>,
+}
+
+impl StateHistory {
+    pub fn iter(&self) -> impl Iterator<Item = &StateHistoryEntry> {
+        self.entries.iter()
+    }
+
+    pub fn get_current_stack(&self) -> Option<&[GameState]> {
+        self.entries.back().map(|e| e.stack.as_slice())
+    }
+}
+
+/// The changes between two stacks, states are exited top to bottom and entered bottom to top
+pub fn get_state_hooks(prev: &[GameState], next: &[GameState]) -> Vec<GameStateHook> {
+    let common_len = prev
+        .iter()
+        .zip(next.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut hooks: Vec<_> = prev[common_len..]
+        .iter()
+        .rev()
+        .map(|s| GameStateHook::Exit(*s))
+        .collect();
+
+    if common_len > 0 {
+        let below = prev[common_len - 1];
+
+        if common_len < prev.len() {
+            hooks.push(GameStateHook::Resume(below));
+        }
+
+        if common_len < next.len() {
+            hooks.push(GameStateHook::Pause(below));
+        }
+    }
+
+    hooks.extend(next[common_len..].iter().map(|s| GameStateHook::Enter(*s)));
+    hooks
+}
+
+fn record_state_history(
+    state: Res<State<GameState>>,
+    mut history: ResMut<StateHistory>,
+    mut hook_evw: EventWriter<GameStateHook>,
+    time: Res<Time>,
+) {
+    let stack = state.get_stack();
+    let prev = history.get_current_stack().unwrap_or_default();
+
+    if prev == stack.as_slice() {
+        return;
+    }
+
+    for hook in get_state_hooks(prev, &stack) {
+        hook_evw.send(hook);
+    }
+
+    debug!("state stack {:?} -> {:?}", prev, stack);
+
+    if history.entries.len() >= STATE_HISTORY_LEN {
+        history.entries.pop_front();
+    }
+
+    history.entries.push_back(StateHistoryEntry {
+        stack,
+        elapsed_sec: time.seconds_since_startup(),
+    });
+}
+
+/// A state change which is applied once its delay has passed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateChange<T> {
+    Set(T),
+    Push(T),
+    Pop,
+}
+
+/// What happens when a change is queued while another one is pending
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueuePolicy {
+    /// The new change replaces the pending one and its delay starts over
+    Replace,
+    /// The pending change is kept and the new one is dropped
+    Ignore,
+}
+
+/// Which time the delay runs on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DelayTime {
+    Real,
+    /// Stops while the game is paused
+    Scaled,
+}
+
+pub struct StateTransitionQueued<T> {
+    pub change: StateChange<T>,
+    pub delay_sec: f32,
+}
+
+pub struct StateTransitionApplied<T> {
+    pub change: StateChange<T>,
+}
+
+struct QueuedChange<T> {
+    change: StateChange<T>,
     timer: Timer,
-    queued_state: Option<T>,
+    time: DelayTime,
+}
+
+pub struct DelayedState<T: StateData> {
+    queued: Option<QueuedChange<T>>,
+    // sent by set_delayed_state, as the queue fns don't have access to the events
+    queued_evts: Vec<StateTransitionQueued<T>>,
 }
 
 impl<T: StateData> Default for DelayedState<T> {
     fn default() -> Self {
         Self {
-            timer: Default::default(),
-            queued_state: Default::default(),
+            queued: None,
+            queued_evts: Vec::new(),
         }
     }
 }
 
 impl<T: StateData + Copy> DelayedState<T> {
+    /// Sets the state after the delay on scaled time, replacing any pending change
     pub fn queue_state(&mut self, state: T, delay_sec: f32) {
-        if let Some(queued) = self.queued_state {
-            if queued == state {
-                return;
-            }
+        self.queue(
+            StateChange::Set(state),
+            delay_sec,
+            QueuePolicy::Replace,
+            DelayTime::Scaled,
+        );
+    }
+
+    /// Returns whether the change was queued
+    pub fn queue(
+        &mut self,
+        change: StateChange<T>,
+        delay_sec: f32,
+        policy: QueuePolicy,
+        time: DelayTime,
+    ) -> bool {
+        if self.queued.is_some() && policy == QueuePolicy::Ignore {
+            return false;
         }
 
-        self.queued_state = Some(state);
-        self.timer = Timer::from_seconds(delay_sec, false);
+        self.queued = Some(QueuedChange {
+            change,
+            timer: Timer::from_seconds(delay_sec, false),
+            time,
+        });
+        self.queued_evts
+            .push(StateTransitionQueued { change, delay_sec });
+
+        true
+    }
+
+    /// Returns the cancelled change
+    pub fn cancel(&mut self) -> Option<StateChange<T>> {
+        self.queued.take().map(|q| q.change)
+    }
+
+    pub fn get_queued(&self) -> Option<StateChange<T>> {
+        self.queued.as_ref().map(|q| q.change)
+    }
+
+    /// Returns the change once its delay has passed
+    pub fn tick(&mut self, real_delta: Duration, scaled_delta: Duration) -> Option<StateChange<T>> {
+        let queued = self.queued.as_mut()?;
+        queued.timer.tick(match queued.time {
+            DelayTime::Real => real_delta,
+            DelayTime::Scaled => scaled_delta,
+        });
+
+        if queued.timer.finished() {
+            self.cancel()
+        } else {
+            None
+        }
     }
 }
 
 pub fn set_delayed_state<T: StateData + Copy>(
     mut delayed_state: ResMut<DelayedState<T>>,
     mut state: ResMut<State<T>>,
+    mut queued_evw: EventWriter<StateTransitionQueued<T>>,
+    mut applied_evw: EventWriter<StateTransitionApplied<T>>,
     time: ScaledTime,
+    replayer: Option<Res<Replayer>>,
 ) {
-    if let Some(next_state) = delayed_state.queued_state {
-        delayed_state.timer.tick(time.scaled_delta());
+    for ev in delayed_state.queued_evts.drain(..) {
+        queued_evw.send(ev);
+    }
+
+    if let Some(change) =
+        delayed_state.tick(time.delta(), get_scaled_delta(&time, replayer.as_deref()))
+    {
+        info!("applying queued state change {:?}", change);
+
+        let res = match change {
+            StateChange::Set(next) => state.overwrite_set(next),
+            StateChange::Push(next) => state.overwrite_push(next),
+            StateChange::Pop => state.overwrite_pop(),
+        };
+
+        match res {
+            Ok(_) => applied_evw.send(StateTransitionApplied { change }),
+            Err(err) => warn!("Queued state change {change:?} failed: {err:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy_time::TimePlugin;
+
+    fn get_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(TimePlugin)
+            .add_state(GameState::Game)
+            .add_plugin(GameStatePlugin);
+        app.update();
+        app
+    }
+
+    fn change_state(
+        app: &mut App,
+        change: impl FnOnce(&mut State<GameState>) -> Result<(), GameStateError>,
+    ) {
+        change(&mut app.world.get_resource_mut::<State<GameState>>().unwrap()).unwrap();
+        app.update();
+    }
+
+    fn get_stack(app: &App) -> Vec<GameState> {
+        app.world
+            .get_resource::<State<GameState>>()
+            .unwrap()
+            .get_stack()
+    }
+
+    #[test]
+    fn repeated_resets_dont_grow_the_stack() {
+        let mut app = get_app();
+
+        for _ in 0..10 {
+            change_state(&mut app, |s| s.go_to(GameState::Reset));
+            change_state(&mut app, |s| s.go_to(GameState::Game));
+        }
+
+        assert_eq!(get_stack(&app), vec![GameState::Game]);
+    }
+
+    #[test]
+    fn overlays_are_removed_when_leaving() {
+        let mut app = get_app();
+
+        for _ in 0..5 {
+            change_state(&mut app, |s| s.open(GameState::Paused));
+            assert_eq!(get_stack(&app), vec![GameState::Game, GameState::Paused]);
+            change_state(&mut app, |s| s.close(GameState::Paused));
+        }
 
-        if delayed_state.timer.finished() {
-            info!("setting queued state");
-            state.overwrite_set(next_state).unwrap();
-            delayed_state.queued_state = None;
+        assert_eq!(get_stack(&app), vec![GameState::Game]);
+
+        // restart and quit from the pause menu
+        change_state(&mut app, |s| s.open(GameState::Paused));
+        change_state(&mut app, |s| s.go_to(GameState::Reset));
+        assert_eq!(get_stack(&app), vec![GameState::Reset]);
+        change_state(&mut app, |s| s.go_to(GameState::Game));
+        change_state(&mut app, |s| s.open(GameState::Disconnected));
+        change_state(&mut app, |s| s.open(GameState::Paused));
+        change_state(&mut app, |s| s.go_to(GameState::Menu));
+
+        assert_eq!(get_stack(&app), vec![GameState::Menu]);
+    }
+
+    #[test]
+    fn invalid_changes_are_rejected() {
+        let mut state = State::new(GameState::Game);
+
+        assert_eq!(
+            state.open(GameState::Menu),
+            Err(GameStateError::NotOverlay(GameState::Menu))
+        );
+        assert_eq!(
+            state.go_to(GameState::Paused),
+            Err(GameStateError::IsOverlay(GameState::Paused))
+        );
+        assert_eq!(
+            state.go_to(GameState::Game),
+            Err(GameStateError::AlreadyInState(GameState::Game))
+        );
+        assert_eq!(
+            state.close(GameState::Paused),
+            Err(GameStateError::NotOnTop(GameState::Paused))
+        );
+    }
+
+    #[test]
+    fn overlays_cant_be_opened_twice() {
+        let mut app = get_app();
+        change_state(&mut app, |s| s.open(GameState::Paused));
+        change_state(&mut app, |s| s.open(GameState::Disconnected));
+
+        let mut state = app.world.get_resource_mut::<State<GameState>>().unwrap();
+        assert_eq!(
+            state.open(GameState::Paused),
+            Err(GameStateError::AlreadyOpen(GameState::Paused))
+        );
+    }
+
+    #[test]
+    fn history_is_recorded_and_bounded() {
+        let mut app = get_app();
+
+        for _ in 0..STATE_HISTORY_LEN {
+            change_state(&mut app, |s| s.open(GameState::Paused));
+            change_state(&mut app, |s| s.close(GameState::Paused));
         }
+
+        let history = app.world.get_resource::<StateHistory>().unwrap();
+        assert_eq!(history.iter().count(), STATE_HISTORY_LEN);
+        assert_eq!(history.get_current_stack(), Some(&[GameState::Game][..]));
+    }
+
+    const FRAME: Duration = Duration::from_millis(100);
+
+    #[test]
+    fn delayed_change_waits_for_its_delay() {
+        let mut delayed = DelayedState::default();
+        delayed.queue_state(GameState::Menu, 0.25);
+
+        assert_eq!(delayed.tick(FRAME, FRAME), None);
+        assert_eq!(delayed.tick(FRAME, FRAME), None);
+        assert_eq!(
+            delayed.tick(FRAME, FRAME),
+            Some(StateChange::Set(GameState::Menu))
+        );
+        assert_eq!(delayed.get_queued(), None);
+    }
+
+    #[test]
+    fn delay_runs_on_the_chosen_time() {
+        let mut delayed = DelayedState::<GameState>::default();
+
+        // paused
+        delayed.queue(
+            StateChange::Pop,
+            0.05,
+            QueuePolicy::Replace,
+            DelayTime::Scaled,
+        );
+        assert_eq!(delayed.tick(FRAME, Duration::ZERO), None);
+        assert_eq!(delayed.tick(Duration::ZERO, FRAME), Some(StateChange::Pop));
+
+        delayed.queue(
+            StateChange::Pop,
+            0.05,
+            QueuePolicy::Replace,
+            DelayTime::Real,
+        );
+        assert_eq!(delayed.tick(Duration::ZERO, FRAME), None);
+        assert_eq!(delayed.tick(FRAME, Duration::ZERO), Some(StateChange::Pop));
+    }
+
+    #[test]
+    fn queue_policy_decides_between_pending_and_new() {
+        let mut delayed = DelayedState::default();
+        let menu = StateChange::Set(GameState::Menu);
+        let pause = StateChange::Push(GameState::Paused);
+
+        assert!(delayed.queue(menu, 0.15, QueuePolicy::Ignore, DelayTime::Real));
+        assert!(!delayed.queue(pause, 0.1, QueuePolicy::Ignore, DelayTime::Real));
+        assert_eq!(delayed.get_queued(), Some(menu));
+
+        delayed.tick(FRAME, FRAME);
+
+        // replacing restarts the delay, even for the same change
+        assert!(delayed.queue(menu, 0.15, QueuePolicy::Replace, DelayTime::Real));
+        assert_eq!(delayed.tick(FRAME, FRAME), None);
+        assert!(delayed.queue(pause, 0.05, QueuePolicy::Replace, DelayTime::Real));
+        assert_eq!(delayed.tick(FRAME, FRAME), Some(pause));
+    }
+
+    #[test]
+    fn cancelled_change_is_never_applied() {
+        let mut delayed = DelayedState::default();
+        delayed.queue_state(GameState::Menu, 0.1);
+
+        assert_eq!(delayed.cancel(), Some(StateChange::Set(GameState::Menu)));
+        assert_eq!(delayed.tick(FRAME, FRAME), None);
+        assert_eq!(delayed.cancel(), None);
+    }
+
+    #[test]
+    fn delayed_change_sends_events() {
+        let mut app = get_app();
+        app.world
+            .get_resource_mut::<DelayedState<GameState>>()
+            .unwrap()
+            .queue(
+                StateChange::Push(GameState::Paused),
+                0.,
+                QueuePolicy::Replace,
+                DelayTime::Real,
+            );
+        app.update();
+        app.update();
+
+        let queued = app
+            .world
+            .get_resource::<Events<StateTransitionQueued<GameState>>>()
+            .unwrap();
+        assert_eq!(queued.get_reader().iter(queued).count(), 1);
+
+        let applied = app
+            .world
+            .get_resource::<Events<StateTransitionApplied<GameState>>>()
+            .unwrap();
+        let applied: Vec<_> = applied
+            .get_reader()
+            .iter(applied)
+            .map(|ev| ev.change)
+            .collect();
+        assert_eq!(applied, vec![StateChange::Push(GameState::Paused)]);
+        assert_eq!(get_stack(&app), vec![GameState::Game, GameState::Paused]);
+    }
+
+    #[test]
+    fn hooks_follow_stack_changes() {
+        use GameState::*;
+        use GameStateHook::*;
+
+        assert_eq!(
+            get_state_hooks(&[Game], &[Game, Paused]),
+            vec![Pause(Game), Enter(Paused)]
+        );
+        assert_eq!(
+            get_state_hooks(&[Game, Paused], &[Game]),
+            vec![Exit(Paused), Resume(Game)]
+        );
+        assert_eq!(
+            get_state_hooks(&[Game, Paused], &[Menu]),
+            vec![Exit(Paused), Exit(Game), Enter(Menu)]
+        );
+        assert_eq!(get_state_hooks(&[], &[Loading]), vec![Enter(Loading)]);
     }
 }
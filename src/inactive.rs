@@ -0,0 +1,325 @@
+// disabling entities for stacking reasons, e.g. a stunned enemy that's also frozen by a cutscene
+
+use crate::{
+    fixed_step::{FixedStep, FixedSystem, FixedUpdateStage},
+    schedule::{PhaseSystem, UpdatePhase},
+};
+use bevy::{ecs::system::Command, prelude::*, utils::HashMap};
+use bevy_time::*;
+use std::time::Duration;
+
+pub struct InactivePlugin;
+impl Plugin for InactivePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Activated>()
+            .add_event::<Deactivated>()
+            .add_system(process_real_time_inactive.in_phase(UpdatePhase::Logic))
+            .add_system_to_stage(FixedUpdateStage, process_gameplay_inactive.in_fixed_step());
+    }
+}
+
+/// Query filter for entities without any [`Inactive`] reason
+pub type Active = Without<Inactive>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InactiveReason {
+    /// Panels waiting for a button action or a submenu to finish
+    Menu,
+    /// Debounce after a button got activated
+    Cooldown,
+    Paused,
+    Cutscene,
+    Stunned,
+    /// Ui panels while a screen transition is running
+    Transition,
+}
+
+impl InactiveReason {
+    /// Ui reasons have to expire while the game is paused, the rest tick with the fixed step
+    fn is_real_time(&self) -> bool {
+        matches!(self, InactiveReason::Menu | InactiveReason::Cooldown)
+    }
+}
+
+/// Present while the entity has at least one reason to be inactive.
+/// Don't insert or remove it directly, go through [`InactiveCommands`], so the reasons stack.
+#[derive(Component, Debug, Default)]
+pub struct Inactive {
+    /// Keyed by the reason and the entity it was applied to, so a reason propagated from an ancestor
+    /// doesn't clear the entity's own reason of the same kind.
+    /// Reasons with a timer expire on their own.
+    reasons: HashMap<(InactiveReason, Entity), Option<Timer>>,
+}
+
+impl Inactive {
+    pub fn has_reason(&self, reason: InactiveReason) -> bool {
+        self.reasons.keys().any(|(r, _)| *r == reason)
+    }
+
+    /// A reason applied by several sources is listed once for every source
+    pub fn get_reasons(&self) -> impl Iterator<Item = &InactiveReason> {
+        self.reasons.keys().map(|(r, _)| r)
+    }
+}
+
+/// Deactivating or activating the entity applies to all of its descendants as well
+#[derive(Component)]
+pub struct PropagateInactive;
+
+/// Sent when the entity loses its last reason
+pub struct Activated(pub Entity);
+
+/// Sent when the entity gets its first reason
+pub struct Deactivated(pub Entity);
+
+pub trait InactiveCommands {
+    fn deactivate(&mut self, entity: Entity, reason: InactiveReason);
+    fn deactivate_for(&mut self, entity: Entity, reason: InactiveReason, duration_sec: f32);
+    fn activate(&mut self, entity: Entity, reason: InactiveReason);
+}
+
+impl InactiveCommands for Commands<'_, '_> {
+    fn deactivate(&mut self, entity: Entity, reason: InactiveReason) {
+        self.add(SetInactive {
+            entity,
+            reason,
+            change: InactiveChange::Add(None),
+        });
+    }
+
+    fn deactivate_for(&mut self, entity: Entity, reason: InactiveReason, duration_sec: f32) {
+        self.add(SetInactive {
+            entity,
+            reason,
+            change: InactiveChange::Add(Some(Timer::from_seconds(duration_sec, false))),
+        });
+    }
+
+    fn activate(&mut self, entity: Entity, reason: InactiveReason) {
+        self.add(SetInactive {
+            entity,
+            reason,
+            change: InactiveChange::Remove,
+        });
+    }
+}
+
+enum InactiveChange {
+    Add(Option<Timer>),
+    Remove,
+}
+
+struct SetInactive {
+    entity: Entity,
+    reason: InactiveReason,
+    change: InactiveChange,
+}
+
+impl Command for SetInactive {
+    fn write(self, world: &mut World) {
+        let mut entities = vec![self.entity];
+
+        if world.get::<PropagateInactive>(self.entity).is_some() {
+            let mut i = 0;
+
+            while i < entities.len() {
+                if let Some(children) = world.get::<Children>(entities[i]) {
+                    entities.extend(children.iter().copied());
+                }
+
+                i += 1;
+            }
+        }
+
+        for e in entities {
+            if world.get_entity(e).is_none() {
+                continue;
+            }
+
+            let key = (self.reason, self.entity);
+
+            match &self.change {
+                InactiveChange::Add(timer) => {
+                    if let Some(mut inactive) = world.get_mut::<Inactive>(e) {
+                        // a permanent reason stays permanent
+                        let is_permanent = matches!(inactive.reasons.get(&key), Some(None));
+
+                        if !is_permanent {
+                            inactive.reasons.insert(key, timer.clone());
+                        }
+                    } else {
+                        let mut inactive = Inactive::default();
+                        inactive.reasons.insert(key, timer.clone());
+                        world.entity_mut(e).insert(inactive);
+                        send_event(world, Deactivated(e));
+                    }
+                }
+                InactiveChange::Remove => {
+                    remove_reason(world, e, key);
+                }
+            }
+        }
+    }
+}
+
+/// Drops a single reason of a single entity once its timer runs out,
+/// every propagated copy of the reason has its own timer
+struct ExpireReason {
+    entity: Entity,
+    key: (InactiveReason, Entity),
+}
+
+impl Command for ExpireReason {
+    fn write(self, world: &mut World) {
+        if world.get_entity(self.entity).is_some() {
+            remove_reason(world, self.entity, self.key);
+        }
+    }
+}
+
+fn remove_reason(world: &mut World, entity: Entity, key: (InactiveReason, Entity)) {
+    let is_empty = match world.get_mut::<Inactive>(entity) {
+        Some(mut inactive) => {
+            inactive.reasons.remove(&key);
+            inactive.reasons.is_empty()
+        }
+        None => return,
+    };
+
+    if is_empty {
+        world.entity_mut(entity).remove::<Inactive>();
+        send_event(world, Activated(entity));
+    }
+}
+
+fn send_event<T: Send + Sync + 'static>(world: &mut World, ev: T) {
+    if let Some(mut events) = world.get_resource_mut::<Events<T>>() {
+        events.send(ev);
+    }
+}
+
+fn process_real_time_inactive(
+    mut commands: Commands,
+    mut inactive_q: Query<(Entity, &mut Inactive)>,
+    time: ScaledTime,
+) {
+    tick_reasons(&mut commands, &mut inactive_q, true, time.delta());
+}
+
+fn process_gameplay_inactive(
+    mut commands: Commands,
+    mut inactive_q: Query<(Entity, &mut Inactive)>,
+    fixed: Res<FixedStep>,
+) {
+    let step = Duration::from_secs_f32(fixed.get_step_sec());
+    tick_reasons(&mut commands, &mut inactive_q, false, step);
+}
+
+fn tick_reasons(
+    commands: &mut Commands,
+    inactive_q: &mut Query<(Entity, &mut Inactive)>,
+    is_real_time: bool,
+    delta: Duration,
+) {
+    for (inactive_e, mut inactive) in inactive_q.iter_mut() {
+        for (key, timer) in inactive.reasons.iter_mut() {
+            if key.0.is_real_time() != is_real_time {
+                continue;
+            }
+
+            if let Some(timer) = timer {
+                timer.tick(delta);
+
+                if timer.just_finished() {
+                    commands.add(ExpireReason {
+                        entity: inactive_e,
+                        key: *key,
+                    });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_app() -> App {
+        let mut app = App::new();
+        app.add_event::<Activated>().add_event::<Deactivated>();
+        app
+    }
+
+    fn apply(app: &mut App, f: impl FnOnce(&mut Commands)) {
+        let mut queue = bevy::ecs::system::CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &app.world);
+        f(&mut commands);
+        queue.apply(&mut app.world);
+    }
+
+    fn get_event_count<T: Send + Sync + 'static>(app: &App) -> usize {
+        let events = app.world.get_resource::<Events<T>>().unwrap();
+        events.get_reader().iter(events).count()
+    }
+
+    #[test]
+    fn reasons_stack() {
+        let mut app = get_app();
+        let e = app.world.spawn().id();
+
+        apply(&mut app, |c| {
+            c.deactivate(e, InactiveReason::Stunned);
+            c.deactivate(e, InactiveReason::Cutscene);
+            c.activate(e, InactiveReason::Stunned);
+        });
+        assert!(app.world.get::<Inactive>(e).is_some());
+        assert_eq!(get_event_count::<Deactivated>(&app), 1);
+        assert_eq!(get_event_count::<Activated>(&app), 0);
+
+        apply(&mut app, |c| c.activate(e, InactiveReason::Cutscene));
+        assert!(app.world.get::<Inactive>(e).is_none());
+        assert_eq!(get_event_count::<Activated>(&app), 1);
+    }
+
+    #[test]
+    fn propagates_to_children() {
+        let mut app = get_app();
+        let child = app.world.spawn().id();
+        let parent = app.world.spawn().insert(PropagateInactive).id();
+        app.world.entity_mut(parent).push_children(&[child]);
+
+        apply(&mut app, |c| c.deactivate(parent, InactiveReason::Paused));
+        assert!(app
+            .world
+            .get::<Inactive>(child)
+            .unwrap()
+            .has_reason(InactiveReason::Paused));
+
+        apply(&mut app, |c| c.activate(parent, InactiveReason::Paused));
+        assert!(app.world.get::<Inactive>(child).is_none());
+    }
+
+    #[test]
+    fn propagated_reason_keeps_own_reason() {
+        let mut app = get_app();
+        let child = app.world.spawn().id();
+        let parent = app.world.spawn().insert(PropagateInactive).id();
+        app.world.entity_mut(parent).push_children(&[child]);
+
+        apply(&mut app, |c| {
+            c.deactivate(child, InactiveReason::Stunned);
+            c.deactivate(parent, InactiveReason::Stunned);
+            c.activate(parent, InactiveReason::Stunned);
+        });
+        assert!(app.world.get::<Inactive>(parent).is_none());
+        assert!(app
+            .world
+            .get::<Inactive>(child)
+            .unwrap()
+            .has_reason(InactiveReason::Stunned));
+
+        apply(&mut app, |c| c.activate(child, InactiveReason::Stunned));
+        assert!(app.world.get::<Inactive>(child).is_none());
+    }
+}
@@ -1,28 +1,44 @@
-use bevy::prelude::*;
+use crate::{
+    schedule::{PhaseSystem, UpdatePhase},
+    settings::Settings,
+    touch::VIRTUAL_STICK_GAMEPAD,
+};
+use bevy::{prelude::*, utils::HashMap};
 use bevy_input::*;
+use bevy_time::*;
+use serde::{Deserialize, Serialize};
 
 pub struct InputBindingPlugin;
 impl Plugin for InputBindingPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.add_action_input_systems::<UiAction>()
-            .add_action_input_systems_with_axis::<PlayerAction, PlayerAxis>();
+        app.init_resource::<UiNavRepeatConfig>()
+            .add_action_input_systems::<UiAction>()
+            .add_action_input_systems_with_axis::<PlayerAction, PlayerAxis>()
+            .add_system(update_ui_nav_input.in_phase(UpdatePhase::Input))
+            .add_system(refresh_menu_input_maps.in_phase(UpdatePhase::Input));
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum UiAction {
     Confirm,
     Cancel,
     Down,
     Up,
+    Left,
+    Right,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum PlayerAction {
     Reset,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+impl PlayerAction {
+    pub const ALL: [PlayerAction; 1] = [PlayerAction::Reset];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum PlayerAxis {
     MoveX,
     MoveY,
@@ -31,20 +47,598 @@ pub enum PlayerAxis {
 pub type PlayerInput = ActionInput<PlayerAction, PlayerAxis>;
 pub type UiInput = ActionInput<UiAction>;
 
-pub fn get_menu_input_map() -> Result<ActionMap<UiAction>, BindingError> {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    GamepadButton(GamepadButtonType),
+}
+
+impl Binding {
+    pub fn is_same_device(&self, other: &Binding) -> bool {
+        matches!(
+            (self, other),
+            (Binding::Key(_), Binding::Key(_))
+                | (Binding::GamepadButton(_), Binding::GamepadButton(_))
+        )
+    }
+
+    pub fn get_name(&self) -> String {
+        match self {
+            Binding::Key(key) => format!("{key:?}"),
+            Binding::GamepadButton(btn) => format!("{btn:?}"),
+        }
+    }
+}
+
+/// Anything a [`Binding`] can be bound to, axes are bound as a pair of negative/positive buttons
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BindingTarget {
+    Ui(UiAction),
+    Player(PlayerAction),
+    AxisNegative(PlayerAxis),
+    AxisPositive(PlayerAxis),
+}
+
+impl BindingTarget {
+    pub fn get_name(&self) -> String {
+        match self {
+            BindingTarget::Ui(action) => format!("Menu {action:?}"),
+            BindingTarget::Player(action) => format!("{action:?}"),
+            BindingTarget::AxisNegative(PlayerAxis::MoveX) => "Move left".to_string(),
+            BindingTarget::AxisPositive(PlayerAxis::MoveX) => "Move right".to_string(),
+            BindingTarget::AxisNegative(PlayerAxis::MoveY) => "Move down".to_string(),
+            BindingTarget::AxisPositive(PlayerAxis::MoveY) => "Move up".to_string(),
+        }
+    }
+
+    /// Menu and gameplay bindings are separate contexts, so they can share inputs
+    fn is_same_context(&self, other: &BindingTarget) -> bool {
+        matches!(self, BindingTarget::Ui(_)) == matches!(other, BindingTarget::Ui(_))
+    }
+}
+
+/// A target which had to give up a binding during a rebind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RebindConflict {
+    pub target: BindingTarget,
+    /// Replaced binding of the rebound target moved over, so a menu action doesn't end up unbound
+    pub swapped: Option<Binding>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RebindError {
+    /// The menu action would be left without any binding on the device
+    WouldUnbind(BindingTarget),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputBindings(Vec<(BindingTarget, Vec<Binding>)>);
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        use Binding::*;
+        use GamepadButtonType::*;
+
+        Self(vec![
+            (
+                BindingTarget::Ui(UiAction::Confirm),
+                vec![
+                    GamepadButton(South),
+                    Key(KeyCode::Return),
+                    Key(KeyCode::Space),
+                ],
+            ),
+            (
+                BindingTarget::Ui(UiAction::Cancel),
+                vec![GamepadButton(West), Key(KeyCode::Escape)],
+            ),
+            (
+                BindingTarget::Ui(UiAction::Up),
+                vec![Key(KeyCode::W), Key(KeyCode::Up), GamepadButton(DPadUp)],
+            ),
+            (
+                BindingTarget::Ui(UiAction::Down),
+                vec![Key(KeyCode::S), Key(KeyCode::Down), GamepadButton(DPadDown)],
+            ),
+            (
+                BindingTarget::Ui(UiAction::Left),
+                vec![Key(KeyCode::A), Key(KeyCode::Left), GamepadButton(DPadLeft)],
+            ),
+            (
+                BindingTarget::Ui(UiAction::Right),
+                vec![
+                    Key(KeyCode::D),
+                    Key(KeyCode::Right),
+                    GamepadButton(DPadRight),
+                ],
+            ),
+            (
+                BindingTarget::Player(PlayerAction::Reset),
+                vec![Key(KeyCode::R), GamepadButton(Select)],
+            ),
+            (
+                BindingTarget::AxisNegative(PlayerAxis::MoveX),
+                vec![Key(KeyCode::A), Key(KeyCode::Left), GamepadButton(DPadLeft)],
+            ),
+            (
+                BindingTarget::AxisPositive(PlayerAxis::MoveX),
+                vec![
+                    Key(KeyCode::D),
+                    Key(KeyCode::Right),
+                    GamepadButton(DPadRight),
+                ],
+            ),
+            (
+                BindingTarget::AxisNegative(PlayerAxis::MoveY),
+                vec![Key(KeyCode::S), Key(KeyCode::Down), GamepadButton(DPadDown)],
+            ),
+            (
+                BindingTarget::AxisPositive(PlayerAxis::MoveY),
+                vec![Key(KeyCode::W), Key(KeyCode::Up), GamepadButton(DPadUp)],
+            ),
+        ])
+    }
+}
+
+impl InputBindings {
+    pub fn get(&self, target: BindingTarget) -> &[Binding] {
+        self.0
+            .iter()
+            .find(|(t, _)| *t == target)
+            .map(|(_, bindings)| bindings.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(BindingTarget, Vec<Binding>)> {
+        self.0.iter()
+    }
+
+    /// Replaces the target's bindings of the same device (keyboard/gamepad) with the given binding.
+    /// Returns the targets the binding got removed from because of a conflict.
+    /// Fails without changing anything if a menu action would be left unbound on the device.
+    pub fn rebind(
+        &mut self,
+        target: BindingTarget,
+        binding: Binding,
+    ) -> Result<Vec<RebindConflict>, RebindError> {
+        let mut replaced: Vec<_> = self
+            .get(target)
+            .iter()
+            .filter(|b| b.is_same_device(&binding) && **b != binding)
+            .copied()
+            .collect();
+        let mut conflicts = Vec::new();
+
+        for (t, bindings) in self.0.iter() {
+            if *t == target || !t.is_same_context(&target) || !bindings.contains(&binding) {
+                continue;
+            }
+
+            let is_last_on_device = !bindings
+                .iter()
+                .any(|b| *b != binding && b.is_same_device(&binding));
+            let swapped = if is_last_on_device && matches!(t, BindingTarget::Ui(_)) {
+                if replaced.is_empty() {
+                    return Err(RebindError::WouldUnbind(*t));
+                }
+
+                Some(replaced.remove(0))
+            } else {
+                None
+            };
+
+            conflicts.push(RebindConflict {
+                target: *t,
+                swapped,
+            });
+        }
+
+        for conflict in conflicts.iter() {
+            if let Some((_, bindings)) = self.0.iter_mut().find(|(t, _)| *t == conflict.target) {
+                bindings.retain(|b| *b != binding);
+                bindings.extend(conflict.swapped);
+            }
+        }
+
+        match self.0.iter_mut().find(|(t, _)| *t == target) {
+            Some((_, bindings)) => {
+                bindings.retain(|b| !b.is_same_device(&binding));
+                bindings.push(binding);
+            }
+            None => self.0.push((target, vec![binding])),
+        }
+
+        Ok(conflicts)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RepeatTiming {
+    pub initial_delay_sec: f32,
+    pub start_interval_sec: f32,
+    pub min_interval_sec: f32,
+    /// Multiplier applied to the interval after every repeat
+    pub acceleration: f32,
+}
+
+impl Default for RepeatTiming {
+    fn default() -> Self {
+        Self {
+            initial_delay_sec: 0.4,
+            start_interval_sec: 0.15,
+            min_interval_sec: 0.05,
+            acceleration: 0.85,
+        }
+    }
+}
+
+/// Hold-to-repeat timings for menu navigation, actions without a timing fire only once per press
+pub struct UiNavRepeatConfig {
+    pub timings: HashMap<UiAction, RepeatTiming>,
+    pub stick_deadzone: f32,
+}
+
+impl Default for UiNavRepeatConfig {
+    fn default() -> Self {
+        let mut timings = HashMap::default();
+
+        for action in [UiAction::Up, UiAction::Down] {
+            timings.insert(action, RepeatTiming::default());
+        }
+
+        // left/right mostly drive sliders, so those repeat a bit faster
+        for action in [UiAction::Left, UiAction::Right] {
+            timings.insert(
+                action,
+                RepeatTiming {
+                    initial_delay_sec: 0.3,
+                    start_interval_sec: 0.1,
+                    min_interval_sec: 0.03,
+                    ..Default::default()
+                },
+            );
+        }
+
+        Self {
+            timings,
+            stick_deadzone: 0.5,
+        }
+    }
+}
+
+struct HeldAction {
+    held_sec: f32,
+    next_fire_sec: f32,
+    interval_sec: f32,
+}
+
+/// Directional menu input combining the bound buttons and the left stick with hold-to-repeat
+#[derive(Component, Default)]
+pub struct UiNavInput {
+    held: HashMap<UiAction, HeldAction>,
+    fired: Vec<UiAction>,
+}
+
+impl UiNavInput {
+    /// True on the initial press and on every repeat while held
+    pub fn just_fired(&self, action: UiAction) -> bool {
+        self.fired.contains(&action)
+    }
+}
+
+// the left stick is handled by UiNavInput
+pub fn get_menu_input_map(bindings: &InputBindings) -> Result<ActionMap<UiAction>, BindingError> {
     let mut map = ActionMap::<UiAction>::new();
-    map.bind_button_action(UiAction::Confirm, GamepadButtonType::South)?
-        .bind_button_action(UiAction::Confirm, KeyCode::Return)?
-        .bind_button_action(UiAction::Confirm, KeyCode::Space)?
-        .bind_button_action(UiAction::Cancel, GamepadButtonType::West)?
-        .bind_button_action(UiAction::Cancel, KeyCode::Escape)?
-        .bind_button_action(UiAction::Up, KeyCode::W)?
-        .bind_button_action(UiAction::Up, KeyCode::Up)?
-        .bind_button_action(UiAction::Down, KeyCode::S)?
-        .bind_button_action(UiAction::Down, KeyCode::Down)?
-        // todo: bind stick as well?
-        .bind_button_action(UiAction::Up, GamepadButtonType::DPadUp)?
-        .bind_button_action(UiAction::Down, GamepadButtonType::DPadDown)?;
+
+    for (target, target_bindings) in bindings.iter() {
+        if let BindingTarget::Ui(action) = target {
+            for binding in target_bindings.iter() {
+                match binding {
+                    Binding::Key(key) => map.bind_button_action(*action, *key)?,
+                    Binding::GamepadButton(btn) => map.bind_button_action(*action, *btn)?,
+                };
+            }
+        }
+    }
 
     Ok(map)
 }
+
+pub type PlayerActionMap = ActionMap<PlayerAction, PlayerAxis>;
+
+/// Device a player slot is assigned to, the keyboard can only ever be used by a single player
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PlayerDevice {
+    Keyboard,
+    Gamepad(Gamepad),
+}
+
+impl PlayerDevice {
+    pub fn get_name(&self) -> String {
+        match self {
+            PlayerDevice::Keyboard => "Keyboard".to_string(),
+            PlayerDevice::Gamepad(gamepad) => format!("Gamepad {}", gamepad.0 + 1),
+        }
+    }
+
+    pub fn uses_binding(&self, binding: &Binding) -> bool {
+        matches!(
+            (self, binding),
+            (PlayerDevice::Keyboard, Binding::Key(_))
+                | (PlayerDevice::Gamepad(_), Binding::GamepadButton(_))
+        )
+    }
+}
+
+/// Builds the map for a player using the given device.
+/// Button bindings come from the settings, the left stick is always bound to movement.
+pub fn get_player_input_map(
+    device: PlayerDevice,
+    bindings: &InputBindings,
+) -> Result<PlayerActionMap, BindingError> {
+    let mut map = PlayerActionMap::new();
+
+    match device {
+        PlayerDevice::Keyboard => map.set_gamepad(VIRTUAL_STICK_GAMEPAD),
+        PlayerDevice::Gamepad(gamepad) => map.set_gamepad(gamepad),
+    };
+
+    for (target, target_bindings) in bindings.iter() {
+        if let BindingTarget::Player(action) = target {
+            for binding in target_bindings.iter().filter(|b| device.uses_binding(b)) {
+                match binding {
+                    Binding::Key(key) => map.bind_button_action(*action, *key)?,
+                    Binding::GamepadButton(btn) => map.bind_button_action(*action, *btn)?,
+                };
+            }
+        }
+    }
+
+    for axis in [PlayerAxis::MoveX, PlayerAxis::MoveY] {
+        let get_device_bindings = |target| {
+            bindings
+                .get(target)
+                .iter()
+                .filter(|b| device.uses_binding(b))
+                .copied()
+                .collect::<Vec<_>>()
+        };
+
+        // pair up the bindings in order, so A/D and Left/Right end up as separate axis bindings
+        let negative = get_device_bindings(BindingTarget::AxisNegative(axis));
+        let positive = get_device_bindings(BindingTarget::AxisPositive(axis));
+
+        for (neg, pos) in negative.into_iter().zip(positive) {
+            match (neg, pos) {
+                (Binding::Key(neg), Binding::Key(pos)) => {
+                    map.bind_axis_buttons(axis, neg, pos)?;
+                }
+                (Binding::GamepadButton(neg), Binding::GamepadButton(pos)) => {
+                    map.bind_axis_buttons(axis, neg, pos)?;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // the keyboard player gets the on-screen touch stick
+    map.bind_axis(PlayerAxis::MoveX, GamepadAxisType::LeftStickX)?
+        .bind_axis(PlayerAxis::MoveY, GamepadAxisType::LeftStickY)?;
+
+    Ok(map)
+}
+
+/// Devices which have just pressed any of the bindings of the target
+pub fn get_devices_just_pressed(
+    target: BindingTarget,
+    bindings: &InputBindings,
+    keys: &Input<KeyCode>,
+    gamepad_btns: &Input<GamepadButton>,
+) -> Vec<PlayerDevice> {
+    let mut devices = Vec::new();
+
+    for binding in bindings.get(target).iter() {
+        match binding {
+            Binding::Key(key) => {
+                if keys.just_pressed(*key) && !devices.contains(&PlayerDevice::Keyboard) {
+                    devices.push(PlayerDevice::Keyboard);
+                }
+            }
+            Binding::GamepadButton(btn) => {
+                for pressed in gamepad_btns.get_just_pressed().filter(|b| b.1 == *btn) {
+                    let device = PlayerDevice::Gamepad(pressed.0);
+                    if !devices.contains(&device) {
+                        devices.push(device);
+                    }
+                }
+            }
+        }
+    }
+
+    devices
+}
+
+fn refresh_menu_input_maps(
+    settings: Res<Settings>,
+    mut map_q: Query<&mut ActionMap<UiAction>>,
+    mut bindings: Local<Option<InputBindings>>,
+) {
+    // other settings change every frame of e.g. a slider drag
+    if !settings.is_changed() || bindings.as_ref() == Some(&settings.bindings) {
+        return;
+    }
+
+    // the maps are built from the bindings when spawned
+    if bindings.replace(settings.bindings.clone()).is_none() {
+        return;
+    }
+
+    for mut panel_map in map_q.iter_mut() {
+        match get_menu_input_map(&settings.bindings) {
+            Ok(map) => *panel_map = map,
+            Err(err) => {
+                warn!("Invalid menu bindings: {err:?}");
+                return;
+            }
+        }
+    }
+}
+
+fn get_stick_held_actions(
+    gamepads: &Gamepads,
+    axes: &Axis<GamepadAxis>,
+    deadzone: f32,
+) -> Vec<UiAction> {
+    let mut actions = Vec::new();
+
+    for gamepad in gamepads.iter() {
+        let x = axes
+            .get(GamepadAxis(*gamepad, GamepadAxisType::LeftStickX))
+            .unwrap_or_default();
+        let y = axes
+            .get(GamepadAxis(*gamepad, GamepadAxisType::LeftStickY))
+            .unwrap_or_default();
+
+        if x <= -deadzone {
+            actions.push(UiAction::Left);
+        } else if x >= deadzone {
+            actions.push(UiAction::Right);
+        }
+
+        if y <= -deadzone {
+            actions.push(UiAction::Down);
+        } else if y >= deadzone {
+            actions.push(UiAction::Up);
+        }
+    }
+
+    actions
+}
+
+fn update_ui_nav_input(
+    mut nav_q: Query<(&UiInput, &mut UiNavInput)>,
+    config: Res<UiNavRepeatConfig>,
+    gamepads: Res<Gamepads>,
+    axes: Res<Axis<GamepadAxis>>,
+    time: ScaledTime,
+) {
+    let stick_actions = get_stick_held_actions(&gamepads, &axes, config.stick_deadzone);
+    // menus have to work while the game is paused
+    let dt = time.delta().as_secs_f32();
+
+    for (input, mut nav) in nav_q.iter_mut() {
+        let nav = &mut *nav;
+        nav.fired.clear();
+
+        for (action, timing) in config.timings.iter() {
+            if !input.pressed(*action) && !stick_actions.contains(action) {
+                nav.held.remove(action);
+                continue;
+            }
+
+            match nav.held.get_mut(action) {
+                Some(held) => {
+                    held.held_sec += dt;
+
+                    if held.held_sec >= held.next_fire_sec {
+                        nav.fired.push(*action);
+                        held.next_fire_sec += held.interval_sec;
+                        held.interval_sec =
+                            (held.interval_sec * timing.acceleration).max(timing.min_interval_sec);
+                    }
+                }
+                None => {
+                    nav.fired.push(*action);
+                    nav.held.insert(
+                        *action,
+                        HeldAction {
+                            held_sec: 0.,
+                            next_fire_sec: timing.initial_delay_sec,
+                            interval_sec: timing.start_interval_sec,
+                        },
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_keys(bindings: &InputBindings, target: BindingTarget) -> Vec<Binding> {
+        bindings
+            .get(target)
+            .iter()
+            .filter(|b| matches!(b, Binding::Key(_)))
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn rebind_swaps_the_last_menu_key() {
+        let confirm = BindingTarget::Ui(UiAction::Confirm);
+        let cancel = BindingTarget::Ui(UiAction::Cancel);
+        let mut bindings = InputBindings::default();
+        bindings
+            .rebind(confirm, Binding::Key(KeyCode::Return))
+            .unwrap();
+
+        let conflicts = bindings
+            .rebind(cancel, Binding::Key(KeyCode::Return))
+            .unwrap();
+
+        assert_eq!(
+            conflicts,
+            vec![RebindConflict {
+                target: confirm,
+                swapped: Some(Binding::Key(KeyCode::Escape)),
+            }]
+        );
+        assert_eq!(
+            get_keys(&bindings, confirm),
+            vec![Binding::Key(KeyCode::Escape)]
+        );
+        assert_eq!(
+            get_keys(&bindings, cancel),
+            vec![Binding::Key(KeyCode::Return)]
+        );
+    }
+
+    #[test]
+    fn rebind_refuses_to_unbind_a_menu_action() {
+        let confirm = BindingTarget::Ui(UiAction::Confirm);
+        let cancel = BindingTarget::Ui(UiAction::Cancel);
+        let reset = BindingTarget::Player(PlayerAction::Reset);
+        let mut bindings = InputBindings(vec![
+            (confirm, vec![Binding::Key(KeyCode::Return)]),
+            (
+                cancel,
+                vec![Binding::GamepadButton(GamepadButtonType::East)],
+            ),
+            (reset, vec![Binding::Key(KeyCode::R)]),
+        ]);
+        let before = bindings.clone();
+
+        // cancel has no key to hand over to confirm
+        assert_eq!(
+            bindings.rebind(cancel, Binding::Key(KeyCode::Return)),
+            Err(RebindError::WouldUnbind(confirm))
+        );
+        assert_eq!(bindings, before);
+
+        // gameplay actions can be left unbound
+        assert_eq!(
+            bindings.rebind(
+                BindingTarget::AxisNegative(PlayerAxis::MoveX),
+                Binding::Key(KeyCode::R)
+            ),
+            Ok(vec![RebindConflict {
+                target: reset,
+                swapped: None,
+            }])
+        );
+        assert!(bindings.get(reset).is_empty());
+    }
+}
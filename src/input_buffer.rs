@@ -0,0 +1,138 @@
+use crate::{
+    input_binding::{PlayerAction, PlayerInput},
+    replay::{get_scaled_delta, Replayer},
+    schedule::{PhaseSystem, UpdatePhase},
+};
+use bevy::{prelude::*, utils::HashMap};
+use bevy_time::*;
+
+pub struct InputBufferPlugin;
+impl Plugin for InputBufferPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_system(buffer_player_input.in_phase(UpdatePhase::Input));
+    }
+}
+
+const DEFAULT_BUFFER_WINDOW_SEC: f32 = 0.15;
+
+/// Keeps pressed actions around for a short window, so presses slightly before they can be used aren't lost.
+/// Lives next to `PlayerInput` and ticks on scaled time.
+#[derive(Component)]
+pub struct InputBuffer {
+    default_window_sec: f32,
+    windows: HashMap<PlayerAction, f32>,
+    /// Remaining time of each buffered action
+    buffered: HashMap<PlayerAction, f32>,
+}
+
+impl Default for InputBuffer {
+    fn default() -> Self {
+        Self::new(DEFAULT_BUFFER_WINDOW_SEC)
+    }
+}
+
+impl InputBuffer {
+    pub fn new(default_window_sec: f32) -> Self {
+        Self {
+            default_window_sec,
+            windows: HashMap::default(),
+            buffered: HashMap::default(),
+        }
+    }
+
+    /// Overrides the buffer window of a single action, a window of 0 disables buffering for it
+    pub fn with_window(mut self, action: PlayerAction, window_sec: f32) -> Self {
+        self.windows.insert(action, window_sec);
+        self
+    }
+
+    pub fn get_window(&self, action: PlayerAction) -> f32 {
+        *self
+            .windows
+            .get(&action)
+            .unwrap_or(&self.default_window_sec)
+    }
+
+    pub fn is_buffered(&self, action: PlayerAction) -> bool {
+        self.buffered.contains_key(&action)
+    }
+
+    /// Returns whether the action has been pressed within its window and clears it, so it's only used once
+    pub fn consume_buffered(&mut self, action: PlayerAction) -> bool {
+        self.buffered.remove(&action).is_some()
+    }
+
+    pub fn clear(&mut self) {
+        self.buffered.clear();
+    }
+
+    fn press(&mut self, action: PlayerAction) {
+        let window_sec = self.get_window(action);
+
+        if window_sec > 0. {
+            self.buffered.insert(action, window_sec);
+        }
+    }
+
+    fn tick(&mut self, delta_sec: f32) {
+        self.buffered.retain(|_, remaining| {
+            *remaining -= delta_sec;
+            *remaining > 0.
+        });
+    }
+}
+
+/// Keeps a condition 'true' for a while after it stops being met (e.g. coyote time after leaving a ledge).
+/// Tick it with the scaled delta, so it follows the time scale.
+#[derive(Debug, Clone, Copy)]
+pub struct GraceWindow {
+    window_sec: f32,
+    since_met_sec: Option<f32>,
+}
+
+impl GraceWindow {
+    pub fn new(window_sec: f32) -> Self {
+        Self {
+            window_sec,
+            since_met_sec: None,
+        }
+    }
+
+    pub fn tick(&mut self, delta_sec: f32, is_met: bool) {
+        self.since_met_sec = if is_met {
+            Some(0.)
+        } else {
+            self.since_met_sec
+                .map(|since| since + delta_sec)
+                .filter(|since| *since <= self.window_sec)
+        };
+    }
+
+    /// The condition is met or stopped being met less than the window ago
+    pub fn is_open(&self) -> bool {
+        self.since_met_sec.is_some()
+    }
+
+    /// Closes the window after it's been used, e.g. so a coyote jump can't be done twice
+    pub fn consume(&mut self) -> bool {
+        self.since_met_sec.take().is_some()
+    }
+}
+
+fn buffer_player_input(
+    mut input_q: Query<(&PlayerInput, &mut InputBuffer)>,
+    time: ScaledTime,
+    replayer: Option<Res<Replayer>>,
+) {
+    let delta_sec = get_scaled_delta(&time, replayer.as_deref()).as_secs_f32();
+
+    for (input, mut buffer) in input_q.iter_mut() {
+        buffer.tick(delta_sec);
+
+        for action in PlayerAction::ALL {
+            if input.just_pressed(action) {
+                buffer.press(action);
+            }
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use crate::{
+    assets::Fonts,
+    game_state::GameStateStack,
+    input_binding::{get_devices_just_pressed, BindingTarget, PlayerDevice, UiAction},
+    palette::{Palette, PaletteColor},
+    player::{PlayerSlots, MAX_PLAYERS},
+    schedule::{PhaseSystem, UpdatePhase},
+    settings::Settings,
+    transition::{TransitionStyle, TransitionTo, DEFAULT_TRANSITION_SEC},
+    GameState,
+};
+use bevy::{input::gamepad::GamepadEventType, prelude::*};
+
+pub struct LobbyPlugin;
+impl Plugin for LobbyPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_system_set(
+            SystemSet::on_enter(GameState::Lobby)
+                .with_system(reset_slots.in_phase(UpdatePhase::Logic))
+                .with_system(spawn_lobby.in_phase(UpdatePhase::Logic)),
+        )
+        .add_system_set(
+            SystemSet::on_update(GameState::Lobby)
+                .with_system(handle_lobby_input.in_phase(UpdatePhase::Logic))
+                .with_system(update_slot_texts.in_phase(UpdatePhase::Animation)),
+        )
+        .add_system_set(
+            SystemSet::on_exit(GameState::Lobby)
+                .with_system(despawn_ui::<LobbyUi>.in_phase(UpdatePhase::Logic)),
+        )
+        .add_system_set(
+            SystemSet::on_enter(GameState::Disconnected)
+                .with_system(spawn_disconnected_overlay.in_phase(UpdatePhase::Logic)),
+        )
+        .add_system_set(
+            SystemSet::on_update(GameState::Disconnected)
+                .with_system(handle_disconnected_input.in_phase(UpdatePhase::Logic)),
+        )
+        .add_system_set(
+            SystemSet::on_exit(GameState::Disconnected)
+                .with_system(despawn_ui::<DisconnectedUi>.in_phase(UpdatePhase::Logic)),
+        )
+        .add_system_set(
+            SystemSet::on_update(GameState::Game)
+                .with_system(open_disconnected_overlay.in_phase(UpdatePhase::Logic)),
+        )
+        .add_system(handle_gamepad_connections.in_phase(UpdatePhase::Input));
+    }
+}
+
+#[derive(Component)]
+struct LobbyUi;
+
+#[derive(Component)]
+struct LobbySlotText(usize);
+
+#[derive(Component)]
+struct DisconnectedUi;
+
+fn reset_slots(mut slots: ResMut<PlayerSlots>) {
+    *slots = PlayerSlots::default();
+}
+
+fn spawn_lobby(mut commands: Commands, fonts: Res<Fonts>, palette: Res<Palette>) {
+    commands
+        .spawn_bundle(get_overlay_node(&palette))
+        .insert(PaletteColor::Background)
+        .insert(LobbyUi)
+        .with_children(|b| {
+            b.spawn_bundle(get_text_bundle(
+                "Press confirm to join",
+                40.,
+                &fonts,
+                &palette,
+            ))
+            .insert(PaletteColor::Text);
+
+            for id in 0..MAX_PLAYERS {
+                b.spawn_bundle(get_text_bundle("", 30., &fonts, &palette))
+                    .insert(LobbySlotText(id))
+                    .insert(PaletteColor::Text);
+            }
+
+            b.spawn_bundle(get_text_bundle(
+                "Confirm again to start, cancel to leave",
+                25.,
+                &fonts,
+                &palette,
+            ))
+            .insert(PaletteColor::Text);
+        });
+}
+
+fn handle_lobby_input(
+    mut slots: ResMut<PlayerSlots>,
+    mut transition_evw: EventWriter<TransitionTo>,
+    settings: Res<Settings>,
+    keys: Res<Input<KeyCode>>,
+    gamepad_btns: Res<Input<GamepadButton>>,
+) {
+    let confirm_devices = get_devices_just_pressed(
+        BindingTarget::Ui(UiAction::Confirm),
+        &settings.bindings,
+        &keys,
+        &gamepad_btns,
+    );
+
+    for device in confirm_devices {
+        if slots.get_id(device).is_some() {
+            transition_evw.send(TransitionTo(
+                GameState::Game,
+                TransitionStyle::Iris,
+                DEFAULT_TRANSITION_SEC,
+            ));
+            return;
+        }
+
+        if let Some(id) = slots.join(device) {
+            info!("{} joined as player {}", device.get_name(), id + 1);
+        }
+    }
+
+    let cancel_devices = get_devices_just_pressed(
+        BindingTarget::Ui(UiAction::Cancel),
+        &settings.bindings,
+        &keys,
+        &gamepad_btns,
+    );
+
+    for device in cancel_devices {
+        if slots.leave(device).is_none() && slots.is_empty() {
+            // nobody has joined, so cancel goes back to the menu
+            transition_evw.send(TransitionTo::fade(GameState::Menu));
+            return;
+        }
+    }
+}
+
+fn update_slot_texts(slots: Res<PlayerSlots>, mut text_q: Query<(&mut Text, &LobbySlotText)>) {
+    if !slots.is_changed() {
+        return;
+    }
+
+    for (mut text, slot_text) in text_q.iter_mut() {
+        text.sections[0].value = match &slots.0[slot_text.0] {
+            Some(slot) => format!("Player {} - {}", slot_text.0 + 1, slot.device.get_name()),
+            None => format!("Player {} - ...", slot_text.0 + 1),
+        };
+    }
+}
+
+fn handle_gamepad_connections(
+    mut gamepad_evr: EventReader<GamepadEvent>,
+    mut slots: ResMut<PlayerSlots>,
+    mut state: ResMut<State<GameState>>,
+) {
+    for GamepadEvent(gamepad, ev_type) in gamepad_evr.iter() {
+        let device = PlayerDevice::Gamepad(*gamepad);
+
+        match ev_type {
+            GamepadEventType::Disconnected => match state.current() {
+                GameState::Lobby => {
+                    slots.leave(device);
+                }
+                GameState::Game => {
+                    if slots.set_connected(device, false).is_some() {
+                        state.open(GameState::Disconnected).unwrap();
+                    }
+                }
+                _ => {
+                    slots.set_connected(device, false);
+                }
+            },
+            GamepadEventType::Connected => {
+                slots.set_connected(device, true);
+
+                if *state.current() == GameState::Disconnected && slots.all_connected() {
+                    state.close(GameState::Disconnected).unwrap();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Catches gamepads that disconnected while another overlay or the reset was up,
+/// or while the pause menu got opened in the same frame.
+/// Runs on update instead of on resume, because going to another state resumes `Game` on the way.
+fn open_disconnected_overlay(mut state: ResMut<State<GameState>>, slots: Res<PlayerSlots>) {
+    if !slots.all_connected() {
+        // the overlay might be queued already
+        state.open(GameState::Disconnected).ok();
+    }
+}
+
+fn spawn_disconnected_overlay(
+    mut commands: Commands,
+    fonts: Res<Fonts>,
+    palette: Res<Palette>,
+    slots: Res<PlayerSlots>,
+) {
+    let disconnected: Vec<_> = slots
+        .iter()
+        .filter(|(_, slot)| !slot.connected)
+        .map(|(id, slot)| format!("Player {} ({})", id + 1, slot.device.get_name()))
+        .collect();
+
+    commands
+        .spawn_bundle(get_overlay_node(&palette))
+        .insert(PaletteColor::Background)
+        .insert(DisconnectedUi)
+        .with_children(|b| {
+            b.spawn_bundle(get_text_bundle(
+                "Controller disconnected",
+                40.,
+                &fonts,
+                &palette,
+            ))
+            .insert(PaletteColor::Text);
+
+            b.spawn_bundle(get_text_bundle(
+                &format!("Reconnect {} to continue", disconnected.join(", ")),
+                25.,
+                &fonts,
+                &palette,
+            ))
+            .insert(PaletteColor::Text);
+
+            b.spawn_bundle(get_text_bundle(
+                "or press cancel to quit to menu",
+                25.,
+                &fonts,
+                &palette,
+            ))
+            .insert(PaletteColor::Text);
+        });
+}
+
+fn handle_disconnected_input(
+    mut state: ResMut<State<GameState>>,
+    settings: Res<Settings>,
+    keys: Res<Input<KeyCode>>,
+    gamepad_btns: Res<Input<GamepadButton>>,
+) {
+    let cancel_devices = get_devices_just_pressed(
+        BindingTarget::Ui(UiAction::Cancel),
+        &settings.bindings,
+        &keys,
+        &gamepad_btns,
+    );
+
+    if !cancel_devices.is_empty() {
+        state.go_to(GameState::Menu).unwrap();
+    }
+}
+
+fn despawn_ui<T: Component>(mut commands: Commands, ui_q: Query<Entity, With<T>>) {
+    for e in ui_q.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
+
+fn get_overlay_node(palette: &Palette) -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+            position_type: PositionType::Absolute,
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            flex_direction: FlexDirection::ColumnReverse,
+            ..Default::default()
+        },
+        color: palette.get_color(&PaletteColor::Background).into(),
+        ..Default::default()
+    }
+}
+
+fn get_text_bundle(text: &str, font_size: f32, fonts: &Fonts, palette: &Palette) -> TextBundle {
+    TextBundle {
+        text: Text::with_section(
+            text,
+            TextStyle {
+                font: fonts.ui.clone(),
+                font_size,
+                color: palette.get_color(&PaletteColor::Text),
+            },
+            Default::default(),
+        ),
+        style: Style {
+            margin: Rect::all(Val::Px(10.)),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
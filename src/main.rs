@@ -12,29 +12,68 @@ use bevy_kira_audio::AudioPlugin;
 use bevy_prototype_lyon::plugin::ShapePlugin;
 use bevy_time::TimePlugin;
 use bevy_tweening::TweeningPlugin;
+use camera::CameraPlugin;
 use debug::DebugPlugin;
+use dialogue::DialoguePlugin;
+use fixed_step::FixedStepPlugin;
 use game_state::{GameState, GameStatePlugin};
 use heron::PhysicsPlugin;
+use inactive::InactivePlugin;
 use input_binding::InputBindingPlugin;
+use input_buffer::InputBufferPlugin;
+use lobby::LobbyPlugin;
 use menu::MenuPlugin;
 use mouse::MousePlugin;
 use palette::PalettePlugin;
 use pause::PausePlugin;
+use picking::PickingPlugin;
+use player::PlayerPlugin;
 use render::{GameDimensions, RenderPlugin};
+use replay::ReplayPlugin;
+use reset::ResetPlugin;
+use rng::RngPlugin;
+use sampling::SamplingPlugin;
+use schedule::SchedulePlugin;
+use settings::SettingsPlugin;
 use sfx::SfxPlugin;
+use touch::TouchPlugin;
+use transform::TransformUtilsPlugin;
+use transition::TransitionPlugin;
+use tutorial::TutorialPlugin;
 use tween::TweenPlugin;
+use widget::WidgetPlugin;
 
 mod assets;
+mod camera;
 mod debug;
+mod dialogue;
+mod fixed_step;
 mod game_state;
+mod inactive;
 mod input_binding;
+mod input_buffer;
+mod lobby;
 mod menu;
 mod mouse;
 mod palette;
 mod pause;
+mod picking;
+mod player;
 mod render;
+mod replay;
+mod reset;
+mod rng;
+mod sampling;
+mod schedule;
+mod settings;
 mod sfx;
+mod storage;
+mod touch;
+mod transform;
+mod transition;
+mod tutorial;
 mod tween;
+mod widget;
 
 // todo:
 const NAME: &str = "Jam Game";
@@ -91,16 +130,39 @@ fn main() {
     app.add_plugin(TimePlugin);
 
     // game plugins
-    app.add_plugin(RenderPlugin)
+    app.add_plugin(SchedulePlugin)
+        .add_plugin(FixedStepPlugin)
+        .add_plugin(RenderPlugin)
+        .add_plugin(CameraPlugin)
+        .add_plugin(SamplingPlugin)
         .add_plugin(AssetsPlugin)
         .add_plugin(GameStatePlugin)
+        .add_plugin(RngPlugin)
+        .add_plugin(ReplayPlugin)
         .add_plugin(InputBindingPlugin)
+        .add_plugin(InputBufferPlugin)
         .add_plugin(MenuPlugin)
+        .add_plugin(WidgetPlugin)
         .add_plugin(MousePlugin)
+        .add_plugin(TouchPlugin)
+        .add_plugin(PickingPlugin)
+        .add_plugin(InactivePlugin)
         .add_plugin(PausePlugin)
+        .add_plugin(PlayerPlugin)
+        .add_plugin(TransformUtilsPlugin)
+        .add_plugin(LobbyPlugin)
+        .add_plugin(ResetPlugin)
         .add_plugin(PalettePlugin)
+        .add_plugin(SettingsPlugin)
         .add_plugin(TweenPlugin)
+        .add_plugin(TransitionPlugin)
+        .add_plugin(DialoguePlugin)
+        .add_plugin(TutorialPlugin)
         .add_plugin(SfxPlugin);
 
+    if cfg!(feature = "dev") {
+        schedule::dump_update_order(&app);
+    }
+
     app.run();
 }
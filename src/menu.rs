@@ -1,13 +1,31 @@
 use crate::assets::Sprites;
-use crate::input_binding::{get_menu_input_map, UiAction, UiInput};
+use crate::dialogue::{DialogueChoiceEvt, StartDialogue, TUTORIAL_DIALOGUE_PATH};
+use crate::game_state::GameStateStack;
+use crate::inactive::{Activated, Active, InactiveCommands, InactiveReason};
+use crate::input_binding::{
+    get_menu_input_map, Binding, BindingTarget, InputBindings, RebindError, UiAction, UiInput,
+    UiNavInput,
+};
+use crate::palette::PaletteTheme;
 use crate::palette::{Palette, PaletteColor};
-use crate::pause::Inactive;
+use crate::reset::Persistent;
+use crate::schedule::{PhaseSystem, UpdatePhase};
+use crate::settings::{SettingKind, Settings, VOLUME_STEP};
+use crate::sfx::AudioBus;
+use crate::touch::TouchTapEvt;
+use crate::transition::TransitionTo;
+use crate::tutorial::{TutorialStepId, TutorialTarget, TutorialTriggerEvt, RESUME_TRIGGER};
 use crate::tween::{delay_tween, TweenDoneAction};
+use crate::widget::{
+    get_slider_fill_bundle, HorizontalInputWidget, Selector, Slider, SliderFill, Toggle,
+    WidgetLabel, WidgetTarget,
+};
 use crate::GameState;
 use crate::{assets::Fonts, tween::UiColorLens};
 use bevy::app::AppExit;
 use bevy::prelude::*;
 use bevy::ui::FocusPolicy;
+use bevy_time::*;
 use bevy_tweening::lens::{TextColorLens, TransformScaleLens, UiPositionLens};
 use bevy_tweening::{component_animator_system, Animator, EaseFunction, Tween, TweeningType};
 use indexmap::IndexSet;
@@ -16,22 +34,47 @@ use std::time::Duration;
 // todo:
 // actual action handler
 
+/// Any key can be bound, so the capture gets cancelled by waiting it out instead of a key
+const REBIND_TIMEOUT_SEC: f32 = 5.;
+
 pub struct MenuPlugin;
 impl Plugin for MenuPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<ButtonInteractionStyles>()
+            .init_resource::<RebindState>()
             .add_event::<ButtonActiveEvt>()
             .add_startup_system(setup_ui)
-            .add_system(component_animator_system::<UiColor>)
-            .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(setup_main_menu))
-            .add_system_set(SystemSet::on_exit(GameState::Menu).with_system(despawn_panels))
+            .add_system(component_animator_system::<UiColor>.in_phase(UpdatePhase::Animation))
+            .add_system_set(
+                SystemSet::on_enter(GameState::Menu)
+                    .with_system(setup_main_menu.in_phase(UpdatePhase::Logic)),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Menu)
+                    .with_system(despawn_panels.in_phase(UpdatePhase::Logic)),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::Paused)
+                    .with_system(setup_pause_menu.in_phase(UpdatePhase::Logic)),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Paused)
+                    .with_system(handle_pause_menu_cancel.in_phase(UpdatePhase::Logic)),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Paused)
+                    .with_system(despawn_panels.in_phase(UpdatePhase::Logic)),
+            )
+            // panels only exist in the main and pause menus, so these run in any state
             .add_system_set(
-                SystemSet::on_update(GameState::Menu)
-                    .with_system(handle_button_interaction)
-                    .with_system(reactivate_button)
-                    .with_system(on_btn_added)
-                    .with_system(handle_ui_input)
-                    .with_system(handle_button_action),
+                SystemSet::new()
+                    .with_system(handle_button_interaction.in_phase(UpdatePhase::Logic))
+                    .with_system(on_btn_added.in_phase(UpdatePhase::Logic))
+                    .with_system(handle_ui_input.in_phase(UpdatePhase::Logic))
+                    .with_system(handle_button_action.in_phase(UpdatePhase::Logic))
+                    .with_system(capture_rebind_input.in_phase(UpdatePhase::Logic))
+                    .with_system(refresh_buttons_on_palette_change.in_phase(UpdatePhase::Animation))
+                    .with_system(update_rebind_labels.in_phase(UpdatePhase::Animation)),
             )
             .add_system_to_stage(CoreStage::PostUpdate, reactivate_button);
     }
@@ -45,10 +88,14 @@ struct Ui {
 #[derive(Component, Clone, Copy)]
 enum ButtonAction {
     ChangeState(GameState),
-    ShowSubmenu {
-        submenu: Submenu,
-        parent_panel_e: Entity,
-    },
+    Resume,
+    Restart,
+    ShowSubmenu(Submenu),
+    Rebind(BindingTarget),
+    ResetBindings,
+    StartDialogue(&'static str),
+    /// Index of a dialogue choice
+    Choose(usize),
     Cancel,
     Quit,
 }
@@ -56,7 +103,7 @@ enum ButtonAction {
 #[derive(Component, Clone, Copy)]
 enum Submenu {
     Settings,
-    Tutorial,
+    Controls,
 }
 
 #[derive(Component)]
@@ -65,7 +112,10 @@ struct Cancelable {
 }
 
 #[derive(Component)]
-struct ButtonTextEntity(Entity);
+struct PausePanel;
+
+#[derive(Component)]
+pub struct ButtonTextEntity(pub Entity);
 
 #[derive(Clone, Copy)]
 struct ButtonStyle {
@@ -89,7 +139,7 @@ impl Default for ButtonStyle {
 }
 
 #[derive(Component, Clone, Copy)]
-struct ButtonInteractionStyles {
+pub struct ButtonInteractionStyles {
     normal: ButtonStyle,
     focus: ButtonStyle,
     active: ButtonStyle,
@@ -149,22 +199,61 @@ impl ButtonInteractionStyles {
 
 pub struct ButtonActiveEvt {
     action: ButtonAction,
+    panel_e: Entity,
 }
 
+#[derive(Clone, Copy)]
+enum PanelLayout {
+    Column,
+    Grid { columns: usize },
+}
+
+struct RebindCapture {
+    target: BindingTarget,
+    panel_e: Entity,
+    // skips the confirm press which started the capture
+    delay: Timer,
+    timeout: Timer,
+}
+
+#[derive(Default)]
+struct RebindState {
+    capture: Option<RebindCapture>,
+}
+
+#[derive(Component)]
+struct RebindInfoText;
+
 #[derive(Clone, Copy)]
 struct SpawnBtnData<'a> {
     text: &'a str,
     is_accent: bool,
     is_focused: bool,
-    action: ButtonAction,
+    kind: BtnKind,
+}
+
+#[derive(Clone, Copy)]
+enum BtnKind {
+    Push(ButtonAction),
+    Slider(Slider),
+    Toggle(Toggle),
+    Selector(Selector),
 }
 
 #[derive(Component)]
-struct UiFocus {
+pub struct UiFocus {
     focusable_entities: IndexSet<Entity>,
     current_focus_index: usize,
 }
 
+impl UiFocus {
+    pub fn get_focused_entity(&self) -> Option<Entity> {
+        self.focusable_entities
+            .get_index(self.current_focus_index)
+            .copied()
+    }
+}
+
 #[derive(Component, Clone, Copy)]
 enum FocusState {
     None,
@@ -173,7 +262,9 @@ enum FocusState {
 }
 
 fn setup_ui(mut commands: Commands) {
-    commands.spawn_bundle(UiCameraBundle::default());
+    commands
+        .spawn_bundle(UiCameraBundle::default())
+        .insert(Persistent);
 
     let root_e = commands
         .spawn_bundle(NodeBundle {
@@ -188,6 +279,7 @@ fn setup_ui(mut commands: Commands) {
             color: Color::NONE.into(),
             ..Default::default()
         })
+        .insert(Persistent)
         .id();
 
     commands.insert_resource(Ui {
@@ -203,16 +295,15 @@ fn setup_main_menu(
     palette: Res<Palette>,
     sprites: Res<Sprites>,
     ui: Res<Ui>,
+    settings: Res<Settings>,
 ) {
     commands.entity(ui.root_e).with_children(|b| {
         let mut focusable_entities = IndexSet::new();
 
         b // menu root
             .spawn_bundle(get_wrapper_node_bundle())
-            .insert(Animator::new(get_panel_tween(true)))
+            .insert(Animator::new(get_panel_tween(true, settings.reduce_motion)))
             .with_children(|b| {
-                let main_panel_e = b.parent_entity();
-
                 b.spawn_bundle(ImageBundle {
                     image: sprites.bevy_logo.clone().into(),
                     style: Style {
@@ -232,27 +323,21 @@ fn setup_main_menu(
                     let mut btns = Vec::from([
                         (
                             "Play",
-                            ButtonAction::ChangeState(GameState::Game),
+                            ButtonAction::ChangeState(GameState::Lobby),
                             base_btn_margin * 3.,
                             1.5,
                             true,
                         ),
                         (
                             "Tutorial",
-                            ButtonAction::ShowSubmenu {
-                                submenu: Submenu::Tutorial,
-                                parent_panel_e: main_panel_e,
-                            },
+                            ButtonAction::StartDialogue(TUTORIAL_DIALOGUE_PATH),
                             base_btn_margin,
                             1.,
                             false,
                         ),
                         (
                             "Settings",
-                            ButtonAction::ShowSubmenu {
-                                submenu: Submenu::Settings,
-                                parent_panel_e: main_panel_e,
-                            },
+                            ButtonAction::ShowSubmenu(Submenu::Settings),
                             base_btn_margin * 2.5,
                             1.,
                             false,
@@ -294,7 +379,7 @@ fn setup_main_menu(
                                     text,
                                     is_accent: *is_accent,
                                     is_focused: i == 0,
-                                    action: *action,
+                                    kind: BtnKind::Push(*action),
                                 },
                                 b,
                                 &fonts,
@@ -305,7 +390,8 @@ fn setup_main_menu(
                     }
                 });
             })
-            .insert(get_menu_input_map().unwrap())
+            .insert(get_menu_input_map(&settings.bindings).unwrap())
+            .insert(UiNavInput::default())
             .insert(UiFocus {
                 focusable_entities,
                 current_focus_index: 0,
@@ -313,10 +399,61 @@ fn setup_main_menu(
     });
 }
 
+fn setup_pause_menu(
+    mut commands: Commands,
+    ui: Res<Ui>,
+    palette: Res<Palette>,
+    fonts: Res<Fonts>,
+    btn_style: Res<ButtonInteractionStyles>,
+    settings: Res<Settings>,
+) {
+    let btns: Vec<_> = [
+        ("Resume", ButtonAction::Resume),
+        ("Settings", ButtonAction::ShowSubmenu(Submenu::Settings)),
+        ("Restart", ButtonAction::Restart),
+        ("Quit to Menu", ButtonAction::ChangeState(GameState::Menu)),
+    ]
+    .iter()
+    .enumerate()
+    .map(|(i, (text, action))| SpawnBtnData {
+        text: *text,
+        is_accent: i == 0,
+        is_focused: i == 0,
+        kind: BtnKind::Push(*action),
+    })
+    .collect();
+
+    let panel_e = spawn_panel(
+        ui.root_e,
+        None,
+        btns,
+        PanelLayout::Column,
+        &mut commands,
+        &palette,
+        &fonts,
+        &btn_style,
+        &settings,
+    );
+    commands.entity(panel_e).insert(PausePanel);
+}
+
+/// The pause panel isn't [`Cancelable`] as there's no panel to go back to, cancel resumes instead
+fn handle_pause_menu_cancel(
+    panel_q: Query<&UiInput, (With<PausePanel>, Active)>,
+    mut state: ResMut<State<GameState>>,
+) {
+    if panel_q
+        .iter()
+        .any(|input| input.just_pressed(UiAction::Cancel))
+    {
+        state.close(GameState::Paused).unwrap();
+    }
+}
+
 fn handle_button_interaction(
     mut commands: Commands,
     button_style: Res<ButtonInteractionStyles>,
-    ui_focus_q: Query<(Entity, &UiFocus), Without<Inactive>>,
+    ui_focus_q: Query<(Entity, &UiFocus), Active>,
     mut interaction_q: Query<
         (
             Entity,
@@ -326,7 +463,7 @@ fn handle_button_interaction(
             Option<&ButtonInteractionStyles>,
             &FocusState,
             &ButtonTextEntity,
-            &ButtonAction,
+            Option<&ButtonAction>,
             &Parent,
         ),
         (
@@ -335,13 +472,14 @@ fn handle_button_interaction(
                 Changed<FocusState>,
                 Added<ButtonTextEntity>,
             )>,
-            Without<Inactive>,
+            Active,
         ),
     >,
     style_q: Query<&Style>,
     text_q: Query<&Text>,
     mut click_evw: EventWriter<ButtonActiveEvt>,
     palette: Res<Palette>,
+    settings: Res<Settings>,
 ) {
     for (focusable_e, focusable) in ui_focus_q.iter() {
         for focus_e in focusable.focusable_entities.iter() {
@@ -357,16 +495,18 @@ fn handle_button_interaction(
                 parent,
             )) = interaction_q.get_mut(*focus_e)
             {
-                if matches!(focus_state, FocusState::Active)
-                    || matches!(interaction, Interaction::Clicked)
-                {
-                    commands.entity(focusable_e).insert(Inactive::Permanent);
-                    commands.entity(button_e).insert(Inactive::Timed {
-                        timer: Timer::from_seconds(0.4, false),
-                    });
-                    click_evw.send(ButtonActiveEvt {
-                        action: *btn_action,
-                    });
+                // widgets handle their own activation
+                if let Some(btn_action) = btn_action {
+                    if matches!(focus_state, FocusState::Active)
+                        || matches!(interaction, Interaction::Clicked)
+                    {
+                        commands.deactivate(focusable_e, InactiveReason::Menu);
+                        commands.deactivate_for(button_e, InactiveReason::Cooldown, 0.4);
+                        click_evw.send(ButtonActiveEvt {
+                            action: *btn_action,
+                            panel_e: focusable_e,
+                        });
+                    }
                 }
 
                 if let Ok(style) = style_q.get(parent.0) {
@@ -384,6 +524,7 @@ fn handle_button_interaction(
                                 .get_override_or_self(btn_style_override)
                                 .get_interaction_style(*interaction, focus_state),
                             &palette,
+                            settings.reduce_motion,
                         );
                     }
                 }
@@ -395,7 +536,7 @@ fn handle_button_interaction(
 fn reactivate_button(
     mut commands: Commands,
     button_style: Res<ButtonInteractionStyles>,
-    removed: RemovedComponents<Inactive>,
+    mut activated_evr: EventReader<Activated>,
     ui_focus_q: Query<&UiFocus>,
     mut interaction_q: Query<
         (
@@ -412,8 +553,9 @@ fn reactivate_button(
     style_q: Query<&Style>,
     text_q: Query<&Text>,
     palette: Res<Palette>,
+    settings: Res<Settings>,
 ) {
-    for inactive_e in removed.iter() {
+    for inactive_e in activated_evr.iter().map(|ev| ev.0) {
         for ui_focus in ui_focus_q.iter() {
             if ui_focus.focusable_entities.contains(&inactive_e) {
                 if let Ok((
@@ -452,6 +594,7 @@ fn reactivate_button(
                                     .get_override_or_self(btn_style_override)
                                     .get_interaction_style(*interaction, &focus_state),
                                 &palette,
+                                settings.reduce_motion,
                             );
                         }
                     }
@@ -464,57 +607,166 @@ fn reactivate_button(
 fn handle_ui_input(
     mut commands: Commands,
     mut panel_q: Query<
-        (Entity, &UiInput, &mut UiFocus, Option<&Cancelable>, &Parent),
-        Without<Inactive>,
+        (
+            Entity,
+            &UiInput,
+            &UiNavInput,
+            &mut UiFocus,
+            Option<&Cancelable>,
+            &Parent,
+        ),
+        Active,
     >,
-    mut focusable_q: Query<(&mut FocusState, &ButtonAction)>,
+    mut focusable_q: Query<(&mut FocusState, Option<&ButtonAction>)>,
+    transform_q: Query<&GlobalTransform>,
+    horizontal_widget_q: Query<(), HorizontalInputWidget>,
+    node_q: Query<&Node>,
+    mut tap_evr: EventReader<TouchTapEvt>,
+    settings: Res<Settings>,
 ) {
-    for (_panel_e, input, mut focusable, cancelable, parent) in panel_q.iter_mut() {
+    let taps: Vec<Vec2> = tap_evr.iter().map(|ev| ev.screen_pos).collect();
+
+    for (_panel_e, input, nav, mut focusable, cancelable, parent) in panel_q.iter_mut() {
         let any_focusables = !focusable.focusable_entities.is_empty();
 
-        if any_focusables && input.just_pressed(UiAction::Confirm) {
+        // a tap focuses the tapped button and confirms it
+        let tapped_index = focusable.focusable_entities.iter().position(|e| {
+            match (node_q.get(*e), transform_q.get(*e)) {
+                (Ok(node), Ok(t)) => taps.iter().any(|tap| {
+                    (*tap - t.translation.truncate())
+                        .abs()
+                        .cmple(node.size * 0.5)
+                        .all()
+                }),
+                _ => false,
+            }
+        });
+
+        if let Some(tapped_index) = tapped_index {
+            set_focus_index(&mut focusable, tapped_index, &mut focusable_q);
+        }
+
+        if any_focusables && (input.just_pressed(UiAction::Confirm) || tapped_index.is_some()) {
             let active_e = focusable.focusable_entities[focusable.current_focus_index];
-            if let Ok((mut focus_state, action)) = focusable_q.get_mut(active_e) {
+            if let Ok((mut focus_state, Some(action))) = focusable_q.get_mut(active_e) {
                 *focus_state = FocusState::Active;
 
                 if matches!(action, ButtonAction::Cancel) {
                     if let Some(cancelable) = cancelable {
-                        despawn_panel(parent.0, cancelable.previous_panel_e.into(), &mut commands);
+                        despawn_panel(
+                            parent.0,
+                            cancelable.previous_panel_e.into(),
+                            settings.reduce_motion,
+                            &mut commands,
+                        );
                     }
                 }
             }
         } else if cancelable.is_some() && input.just_pressed(UiAction::Cancel) {
             if let Some(cancelable) = cancelable {
-                despawn_panel(parent.0, cancelable.previous_panel_e.into(), &mut commands);
+                despawn_panel(
+                    parent.0,
+                    cancelable.previous_panel_e.into(),
+                    settings.reduce_motion,
+                    &mut commands,
+                );
             }
         } else if any_focusables {
-            let mut focus_offset: i8 = 0;
-            if input.just_pressed(UiAction::Down) {
-                focus_offset = 1;
+            let current_e = focusable.focusable_entities[focusable.current_focus_index];
+            let mut dir = Vec2::ZERO;
+
+            // ui y goes up
+            if nav.just_fired(UiAction::Down) {
+                dir.y -= 1.;
             }
-            if input.just_pressed(UiAction::Up) {
-                focus_offset = -1;
+            if nav.just_fired(UiAction::Up) {
+                dir.y += 1.;
             }
 
-            if focus_offset != 0 {
-                focusable.current_focus_index = (focusable.current_focus_index as i8 + focus_offset)
-                    .rem_euclid(focusable.focusable_entities.len() as i8)
-                    as usize;
-
-                for (i, focus_state_e) in focusable.focusable_entities.iter().enumerate() {
-                    if let Ok((mut focus_state, _)) = focusable_q.get_mut(*focus_state_e) {
-                        *focus_state = if i == focusable.current_focus_index {
-                            FocusState::Focus
-                        } else {
-                            FocusState::None
-                        };
-                    }
+            // sliders and selectors use left/right to change their value
+            if horizontal_widget_q.get(current_e).is_err() {
+                if nav.just_fired(UiAction::Left) {
+                    dir.x -= 1.;
                 }
+                if nav.just_fired(UiAction::Right) {
+                    dir.x += 1.;
+                }
+            }
+
+            if dir == Vec2::ZERO {
+                continue;
+            }
+
+            let next_index = get_focus_index_in_dir(&focusable, dir, &transform_q).or_else(|| {
+                // wrap around vertical lists like before
+                if dir.x == 0. {
+                    Some(
+                        (focusable.current_focus_index as isize - dir.y as isize)
+                            .rem_euclid(focusable.focusable_entities.len() as isize)
+                            as usize,
+                    )
+                } else {
+                    None
+                }
+            });
+
+            if let Some(next_index) = next_index {
+                set_focus_index(&mut focusable, next_index, &mut focusable_q);
             }
         }
     }
 }
 
+fn set_focus_index(
+    focusable: &mut UiFocus,
+    index: usize,
+    focusable_q: &mut Query<(&mut FocusState, Option<&ButtonAction>)>,
+) {
+    focusable.current_focus_index = index;
+
+    for (i, focus_state_e) in focusable.focusable_entities.iter().enumerate() {
+        if let Ok((mut focus_state, _)) = focusable_q.get_mut(*focus_state_e) {
+            *focus_state = if i == index {
+                FocusState::Focus
+            } else {
+                FocusState::None
+            };
+        }
+    }
+}
+
+/// Picks the closest focusable in the given direction based on the node layout positions.
+/// Focusables are compared by their global position, so they can live in any nested container.
+fn get_focus_index_in_dir(
+    focusable: &UiFocus,
+    dir: Vec2,
+    transform_q: &Query<&GlobalTransform>,
+) -> Option<usize> {
+    let dir = dir.normalize();
+    let current_e = focusable.get_focused_entity()?;
+    let current_pos = transform_q.get(current_e).ok()?.translation.truncate();
+
+    focusable
+        .focusable_entities
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| **e != current_e)
+        .filter_map(|(i, e)| {
+            let delta = transform_q.get(*e).ok()?.translation.truncate() - current_pos;
+            let along = delta.dot(dir);
+
+            if along <= 1. {
+                return None;
+            }
+
+            // prefer items in line with the direction over closer ones off to the side
+            let across = delta.perp_dot(dir).abs();
+            Some((i, along + across * 2.))
+        })
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(i, _)| i)
+}
+
 fn on_btn_added(
     mut commands: Commands,
     btn_q: Query<(Entity, &Parent), (Added<FocusState>, With<Button>)>,
@@ -540,34 +792,69 @@ fn handle_button_action(
     btn_style: Res<ButtonInteractionStyles>,
     mut btn_action_evr: EventReader<ButtonActiveEvt>,
     mut state: ResMut<State<GameState>>,
+    mut transition_evw: EventWriter<TransitionTo>,
+    mut dialogue_evw: EventWriter<StartDialogue>,
+    mut choice_evw: EventWriter<DialogueChoiceEvt>,
+    mut tutorial_evw: EventWriter<TutorialTriggerEvt>,
+    mut settings: ResMut<Settings>,
+    mut rebind: ResMut<RebindState>,
+    mut info_q: Query<&mut Text, With<RebindInfoText>>,
     mut exit: EventWriter<AppExit>,
 ) {
     for ev in btn_action_evr.iter() {
         match ev.action {
             ButtonAction::ChangeState(game_state) => {
-                state.overwrite_set(game_state).unwrap();
+                transition_evw.send(TransitionTo::fade(game_state));
+            }
+            ButtonAction::Resume => {
+                state.close(GameState::Paused).unwrap();
+                tutorial_evw.send(TutorialTriggerEvt(RESUME_TRIGGER));
+            }
+            ButtonAction::Restart => {
+                state.go_to(GameState::Reset).unwrap();
             }
-            ButtonAction::ShowSubmenu {
-                submenu,
-                parent_panel_e,
-            } => match submenu {
+            ButtonAction::ShowSubmenu(submenu) => match submenu {
                 Submenu::Settings => spawn_settings(
                     ui.root_e,
-                    parent_panel_e,
+                    ev.panel_e,
                     &mut commands,
                     &palette,
                     &fonts,
                     &btn_style,
+                    &settings,
                 ),
-                Submenu::Tutorial => spawn_tutorial(
+                Submenu::Controls => spawn_controls(
                     ui.root_e,
-                    parent_panel_e,
+                    ev.panel_e,
                     &mut commands,
                     &palette,
                     &fonts,
                     &btn_style,
+                    &settings,
                 ),
             },
+            ButtonAction::Rebind(target) => {
+                rebind.capture = Some(RebindCapture {
+                    target,
+                    panel_e: ev.panel_e,
+                    delay: Timer::from_seconds(0.2, false),
+                    timeout: Timer::from_seconds(REBIND_TIMEOUT_SEC, false),
+                });
+                set_rebind_info(&mut info_q, get_capture_info(target, REBIND_TIMEOUT_SEC));
+            }
+            ButtonAction::ResetBindings => {
+                settings.bindings = InputBindings::default();
+                commands.activate(ev.panel_e, InactiveReason::Menu);
+                set_rebind_info(&mut info_q, "Bindings reset to defaults".to_string());
+            }
+            ButtonAction::StartDialogue(path) => {
+                // the dialogue keeps the panel disabled until it's over
+                commands.activate(ev.panel_e, InactiveReason::Menu);
+                dialogue_evw.send(StartDialogue::new(path));
+            }
+            ButtonAction::Choose(index) => {
+                choice_evw.send(DialogueChoiceEvt(index));
+            }
             ButtonAction::Cancel => {
                 trace!("cancelling");
             }
@@ -576,9 +863,164 @@ fn handle_button_action(
     }
 }
 
-fn despawn_panels(mut commands: Commands, panel_q: Query<Entity, With<UiFocus>>) {
+fn capture_rebind_input(
+    mut commands: Commands,
+    mut rebind: ResMut<RebindState>,
+    mut settings: ResMut<Settings>,
+    keys: Res<Input<KeyCode>>,
+    gamepad_btns: Res<Input<GamepadButton>>,
+    mut info_q: Query<&mut Text, With<RebindInfoText>>,
+    time: ScaledTime,
+) {
+    let (target, panel_e, is_timed_out) = match rebind.capture.as_mut() {
+        Some(capture) => {
+            capture.delay.tick(time.delta());
+            if !capture.delay.finished() {
+                return;
+            }
+
+            capture.timeout.tick(time.delta());
+            (capture.target, capture.panel_e, capture.timeout.finished())
+        }
+        None => return,
+    };
+
+    let info = if is_timed_out {
+        "Rebinding cancelled".to_string()
+    } else if let Some(binding) = keys
+        .get_just_pressed()
+        .next()
+        .map(|key| Binding::Key(*key))
+        .or_else(|| {
+            gamepad_btns
+                .get_just_pressed()
+                .next()
+                .map(|btn| Binding::GamepadButton(btn.1))
+        })
+    {
+        match settings.bindings.rebind(target, binding) {
+            Ok(conflicts) => {
+                let mut info = format!("{} bound to {}", target.get_name(), binding.get_name());
+
+                for conflict in conflicts.iter() {
+                    info.push_str(&match conflict.swapped {
+                        Some(swapped) => format!(
+                            ", {} moved to {}",
+                            conflict.target.get_name(),
+                            swapped.get_name()
+                        ),
+                        None => format!(", unbound from {}", conflict.target.get_name()),
+                    });
+                }
+
+                info
+            }
+            Err(RebindError::WouldUnbind(conflict)) => format!(
+                "{} is the last {} binding of {}",
+                binding.get_name(),
+                get_device_name(&binding),
+                conflict.get_name()
+            ),
+        }
+    } else {
+        if let Some(capture) = rebind.capture.as_ref() {
+            set_rebind_info(
+                &mut info_q,
+                get_capture_info(
+                    target,
+                    capture.timeout.duration().as_secs_f32() - capture.timeout.elapsed_secs(),
+                ),
+            );
+        }
+
+        return;
+    };
+
+    rebind.capture = None;
+    commands.activate(panel_e, InactiveReason::Menu);
+    set_rebind_info(&mut info_q, info);
+}
+
+fn get_capture_info(target: BindingTarget, remaining_sec: f32) -> String {
+    format!(
+        "Press a key or button for {} (cancels in {}s)",
+        target.get_name(),
+        remaining_sec.ceil()
+    )
+}
+
+fn get_device_name(binding: &Binding) -> &'static str {
+    match binding {
+        Binding::Key(_) => "keyboard",
+        Binding::GamepadButton(_) => "gamepad",
+    }
+}
+
+fn set_rebind_info(info_q: &mut Query<&mut Text, With<RebindInfoText>>, info: String) {
+    for mut text in info_q.iter_mut() {
+        // the countdown sets the same text most frames
+        if text.sections[0].value != info {
+            text.sections[0].value = info.clone();
+        }
+    }
+}
+
+fn get_rebind_label(target: BindingTarget, bindings: &InputBindings) -> String {
+    let target_bindings = bindings.get(target);
+    let get_first_name = |is_key: bool| {
+        target_bindings
+            .iter()
+            .find(|b| matches!(b, Binding::Key(_)) == is_key)
+            .map(|b| b.get_name())
+            .unwrap_or_else(|| "-".to_string())
+    };
+
+    format!(
+        "{}: {} / {}",
+        target.get_name(),
+        get_first_name(true),
+        get_first_name(false)
+    )
+}
+
+fn update_rebind_labels(
+    settings: Res<Settings>,
+    btn_q: Query<(&ButtonAction, &ButtonTextEntity)>,
+    mut text_q: Query<&mut Text>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    for (action, btn_txt_e) in btn_q.iter() {
+        if let ButtonAction::Rebind(target) = action {
+            if let Ok(mut text) = text_q.get_mut(btn_txt_e.0) {
+                text.sections[0].value =
+                    get_rebind_label(*target, &settings.bindings).to_uppercase();
+            }
+        }
+    }
+}
+
+fn refresh_buttons_on_palette_change(
+    palette: Res<Palette>,
+    mut focus_q: Query<&mut FocusState, Active>,
+) {
+    if palette.is_changed() && !palette.is_added() {
+        // retween all buttons to the new colors
+        for mut focus_state in focus_q.iter_mut() {
+            focus_state.set_changed();
+        }
+    }
+}
+
+fn despawn_panels(
+    mut commands: Commands,
+    panel_q: Query<Entity, With<UiFocus>>,
+    settings: Res<Settings>,
+) {
     for panel_e in panel_q.iter() {
-        despawn_panel(panel_e, None, &mut commands);
+        despawn_panel(panel_e, None, settings.reduce_motion, &mut commands);
     }
 }
 
@@ -593,7 +1035,14 @@ fn tween_button(
     start_ui_pos: Rect<Val>,
     style: &ButtonStyle,
     palette: &Palette,
+    reduce_motion: bool,
 ) {
+    let (end_scale, end_ui_pos) = if reduce_motion {
+        (Vec2::ONE, Rect::all(Val::Px(0.)))
+    } else {
+        (style.scale, style.position)
+    };
+
     commands
         .entity(button_e)
         .insert(Animator::new(delay_tween(
@@ -615,7 +1064,7 @@ fn tween_button(
                 Duration::from_millis(350),
                 TransformScaleLens {
                     start: start_scale,
-                    end: style.scale.extend(start_scale.z),
+                    end: end_scale.extend(start_scale.z),
                 },
             ),
             style.delay_ms,
@@ -646,7 +1095,7 @@ fn tween_button(
                 Duration::from_millis(350),
                 UiPositionLens {
                     start: start_ui_pos,
-                    end: style.position,
+                    end: end_ui_pos,
                 },
             ),
             style.delay_ms,
@@ -660,91 +1109,178 @@ fn spawn_settings(
     palette: &Palette,
     fonts: &Fonts,
     btn_style: &ButtonInteractionStyles,
+    settings: &Settings,
 ) {
+    let setting_labels: Vec<_> = [
+        SettingKind::Volume(AudioBus::Master),
+        SettingKind::Volume(AudioBus::Music),
+        SettingKind::Volume(AudioBus::Sfx),
+        SettingKind::Fullscreen,
+        SettingKind::Vsync,
+        SettingKind::PaletteTheme,
+        SettingKind::ReduceMotion,
+    ]
+    .into_iter()
+    .map(|kind| (kind, kind.get_name()))
+    .collect();
+
+    let mut btns: Vec<_> = setting_labels
+        .iter()
+        .enumerate()
+        .map(|(i, (kind, label))| SpawnBtnData {
+            kind: get_setting_btn_kind(*kind, settings),
+            is_accent: false,
+            is_focused: i == 0,
+            text: label,
+        })
+        .collect();
+
+    btns.push(SpawnBtnData {
+        kind: BtnKind::Push(ButtonAction::ShowSubmenu(Submenu::Controls)),
+        is_accent: false,
+        is_focused: false,
+        text: "Controls",
+    });
+
+    btns.push(SpawnBtnData {
+        kind: BtnKind::Push(ButtonAction::Cancel),
+        is_accent: false,
+        is_focused: false,
+        text: "Back",
+    });
+
     // root
     let _submenu_root_e = spawn_panel(
         root_e,
         Some(previous_panel_e),
-        vec![SpawnBtnData {
-            action: ButtonAction::Cancel,
-            is_accent: false,
-            is_focused: true,
-            text: "Back",
-        }],
+        btns,
+        PanelLayout::Column,
         commands,
         palette,
         fonts,
         btn_style,
+        settings,
     );
 }
 
-fn spawn_tutorial(
+fn spawn_controls(
     root_e: Entity,
     previous_panel_e: Entity,
     commands: &mut Commands,
     palette: &Palette,
     fonts: &Fonts,
     btn_style: &ButtonInteractionStyles,
+    settings: &Settings,
 ) {
-    // root
+    let rebind_labels: Vec<_> = settings
+        .bindings
+        .iter()
+        .map(|(target, _)| (*target, get_rebind_label(*target, &settings.bindings)))
+        .collect();
+
+    let mut btns: Vec<_> = rebind_labels
+        .iter()
+        .enumerate()
+        .map(|(i, (target, label))| SpawnBtnData {
+            kind: BtnKind::Push(ButtonAction::Rebind(*target)),
+            is_accent: false,
+            is_focused: i == 0,
+            text: label,
+        })
+        .collect();
+
+    btns.push(SpawnBtnData {
+        kind: BtnKind::Push(ButtonAction::ResetBindings),
+        is_accent: false,
+        is_focused: false,
+        text: "Reset to defaults",
+    });
+
+    btns.push(SpawnBtnData {
+        kind: BtnKind::Push(ButtonAction::Cancel),
+        is_accent: false,
+        is_focused: false,
+        text: "Back",
+    });
+
     let submenu_root_e = spawn_panel(
         root_e,
         Some(previous_panel_e),
-        vec![SpawnBtnData {
-            action: ButtonAction::Cancel,
-            is_accent: false,
-            is_focused: true,
-            text: "Back",
-        }],
+        btns,
+        PanelLayout::Grid { columns: 2 },
         commands,
         palette,
         fonts,
         btn_style,
+        settings,
     );
 
     commands.entity(submenu_root_e).with_children(|b| {
         b.spawn_bundle(TextBundle {
-            text: Text {
-                sections: vec![TextSection {
-                    value:
-"Tutorial thingy goes here.
-Make it good...
-Maybe just use an img or better yet, do it in game
-
-Eiusmod consectetur sunt enim duis consequat ullamco pariatur magna. Culpa fugiat excepteur pariatur dolore tempor ad nisi aliqua est aute. Non anim ad esse sit aute ex laboris nulla nostrud sint labore sunt laboris ullamco. Ut cillum eu aute nulla incididunt voluptate.
-
-Aute id incididunt consequat incididunt irure sint eu labore enim. Pariatur ipsum veniam adipisicing amet adipisicing sint consequat exercitation officia nostrud ullamco. Labore id exercitation do consequat cupidatat sit. Eu ipsum anim ullamco magna reprehenderit anim aliqua laborum nisi est qui magna eu dolore. Deserunt veniam cillum id culpa eiusmod magna esse voluptate ad reprehenderit.
-
-Officia nisi non nostrud occaecat amet. Voluptate aute excepteur quis irure sit incididunt. Laboris ex ullamco non excepteur excepteur. Cillum pariatur dolore occaecat proident proident in fugiat excepteur nulla incididunt exercitation proident ex Lorem. Officia duis consectetur ea quis nulla. Enim culpa culpa cillum excepteur sint commodo.".to_string(),
-                    style: TextStyle {
-                        font: fonts.ui.clone(),
-                        font_size: 25.,
-                        color: palette.get_color(&btn_style.normal.text_color),
-                    },
-                }],
-                alignment: Default::default(),
-            },
+            text: Text::with_section(
+                "Select an action to rebind it",
+                TextStyle {
+                    font: fonts.ui.clone(),
+                    font_size: 25.,
+                    color: palette.get_color(&btn_style.normal.text_color),
+                },
+                Default::default(),
+            ),
             focus_policy: FocusPolicy::Pass,
             ..Default::default()
-        });
+        })
+        .insert(RebindInfoText);
     });
 }
 
+fn get_setting_btn_kind(kind: SettingKind, settings: &Settings) -> BtnKind {
+    let target = WidgetTarget::Setting(kind);
+
+    match kind {
+        SettingKind::Volume(bus) => BtnKind::Slider(Slider {
+            value: settings.get_volume(bus),
+            min: 0.,
+            max: 1.,
+            step: VOLUME_STEP,
+            target,
+        }),
+        SettingKind::Fullscreen => BtnKind::Toggle(Toggle {
+            on: settings.fullscreen,
+            target,
+        }),
+        SettingKind::Vsync => BtnKind::Toggle(Toggle {
+            on: settings.vsync,
+            target,
+        }),
+        SettingKind::ReduceMotion => BtnKind::Toggle(Toggle {
+            on: settings.reduce_motion,
+            target,
+        }),
+        SettingKind::PaletteTheme => BtnKind::Selector(Selector {
+            index: settings.palette_theme.get_index(),
+            options: &PaletteTheme::NAMES,
+            target,
+        }),
+    }
+}
+
 fn spawn_panel(
     root_e: Entity,
     previous_panel_e: Option<Entity>,
     btns: Vec<SpawnBtnData>,
+    layout: PanelLayout,
     commands: &mut Commands,
     palette: &Palette,
     fonts: &Fonts,
     btn_style: &ButtonInteractionStyles,
+    settings: &Settings,
 ) -> Entity {
     let mut submenu = None;
 
     commands.entity(root_e).with_children(|b| {
         // root
         b.spawn_bundle(get_wrapper_node_bundle())
-            .insert(Animator::new(get_panel_tween(true)))
+            .insert(Animator::new(get_panel_tween(true, settings.reduce_motion)))
             .with_children(|b| {
                 let margin = 50.;
 
@@ -762,17 +1298,29 @@ fn spawn_panel(
                 });
 
                 let mut focusable_entities = IndexSet::new();
+                let (btns_wrapper, btn_width) = match layout {
+                    PanelLayout::Column => (get_btns_wrapper_node(), Val::Percent(50.)),
+                    PanelLayout::Grid { columns } => (
+                        get_btns_grid_wrapper_node(),
+                        Val::Percent(90. / columns as f32),
+                    ),
+                };
 
                 panel
                     .with_children(|b| {
-                        b.spawn_bundle(get_btns_wrapper_node()).with_children(|b| {
+                        b.spawn_bundle(btns_wrapper).with_children(|b| {
                             for btn in btns.iter() {
-                                focusable_entities
-                                    .insert(spawn_btn(*btn, b, fonts, palette, btn_style));
+                                b.spawn_bundle(get_btn_wrapper_node(btn_style, btn_width))
+                                    .with_children(|b| {
+                                        focusable_entities
+                                            .insert(spawn_btn(*btn, b, fonts, palette, btn_style));
+                                    });
                             }
                         });
                     })
-                    .insert(get_menu_input_map().unwrap())
+                    .insert(PaletteColor::Background)
+                    .insert(get_menu_input_map(&settings.bindings).unwrap())
+                    .insert(UiNavInput::default())
                     .insert(UiFocus {
                         focusable_entities,
                         current_focus_index: 0,
@@ -789,18 +1337,71 @@ fn spawn_panel(
     submenu.unwrap()
 }
 
-fn despawn_panel(panel_e: Entity, previous_panel_e: Option<Entity>, commands: &mut Commands) {
+/// Focusable column of buttons which sends [`DialogueChoiceEvt`] with the picked index.
+/// It's spawned as a child of the given entity, so it gets despawned along with it.
+pub fn spawn_choice_buttons(
+    parent_e: Entity,
+    choices: &[&str],
+    commands: &mut Commands,
+    palette: &Palette,
+    fonts: &Fonts,
+    btn_style: &ButtonInteractionStyles,
+    settings: &Settings,
+) -> Entity {
+    let mut panel_e = None;
+
+    commands.entity(parent_e).with_children(|b| {
+        let mut focusable_entities = IndexSet::new();
+        let mut panel = b.spawn_bundle(get_btns_wrapper_node());
+
+        panel
+            .with_children(|b| {
+                for (i, text) in choices.iter().enumerate() {
+                    b.spawn_bundle(get_btn_wrapper_node(btn_style, Val::Percent(50.)))
+                        .with_children(|b| {
+                            focusable_entities.insert(spawn_btn(
+                                SpawnBtnData {
+                                    text: *text,
+                                    is_accent: false,
+                                    is_focused: i == 0,
+                                    kind: BtnKind::Push(ButtonAction::Choose(i)),
+                                },
+                                b,
+                                fonts,
+                                palette,
+                                btn_style,
+                            ));
+                        });
+                }
+            })
+            .insert(get_menu_input_map(&settings.bindings).unwrap())
+            .insert(UiNavInput::default())
+            .insert(UiFocus {
+                focusable_entities,
+                current_focus_index: 0,
+            });
+
+        panel_e = Some(panel.id());
+    });
+
+    panel_e.unwrap()
+}
+
+fn despawn_panel(
+    panel_e: Entity,
+    previous_panel_e: Option<Entity>,
+    reduce_motion: bool,
+    commands: &mut Commands,
+) {
     if let Some(previous_panel_e) = previous_panel_e {
-        commands.entity(previous_panel_e).remove::<Inactive>();
+        commands.activate(previous_panel_e, InactiveReason::Menu);
     }
 
-    commands
-        .entity(panel_e)
-        .insert(Animator::new(get_panel_tween(false).with_completed_event(
-            true,
-            TweenDoneAction::DespawnRecursive.into(),
-        )))
-        .insert(Inactive::Permanent);
+    commands.entity(panel_e).insert(Animator::new(
+        get_panel_tween(false, reduce_motion)
+            .with_completed_event(true, TweenDoneAction::DespawnRecursive.into()),
+    ));
+    commands.deactivate(panel_e, InactiveReason::Menu);
 }
 
 fn spawn_btn(
@@ -822,7 +1423,40 @@ fn spawn_btn(
         ..Default::default()
     });
 
-    btn.insert(btn_data.action);
+    let text = match btn_data.kind {
+        BtnKind::Push(action) => {
+            btn.insert(action);
+
+            if let ButtonAction::Resume = action {
+                btn.insert(TutorialTarget(TutorialStepId::Resume));
+            }
+
+            btn_data.text.to_string()
+        }
+        BtnKind::Slider(slider) => {
+            btn.insert(slider).with_children(|b| {
+                b.spawn_bundle(get_slider_fill_bundle(
+                    &slider,
+                    palette.get_color(&PaletteColor::ButtonAccent),
+                ))
+                .insert(SliderFill)
+                .insert(PaletteColor::ButtonAccent);
+            });
+            slider.get_text(btn_data.text)
+        }
+        BtnKind::Toggle(toggle) => {
+            btn.insert(toggle);
+            toggle.get_text(btn_data.text)
+        }
+        BtnKind::Selector(selector) => {
+            btn.insert(selector);
+            selector.get_text(btn_data.text)
+        }
+    };
+
+    if !matches!(btn_data.kind, BtnKind::Push(_)) {
+        btn.insert(WidgetLabel(btn_data.text.to_string()));
+    }
 
     if btn_data.is_accent {
         btn.insert(ButtonInteractionStyles {
@@ -862,7 +1496,7 @@ fn spawn_btn(
     child_builder.spawn_bundle(TextBundle {
         text: Text {
             sections: vec![TextSection {
-                value: btn_data.text.to_uppercase(),
+                value: text.to_uppercase(),
                 style: TextStyle {
                     font: fonts.ui.clone(),
                     font_size: 40. * if btn_data.is_accent { 1.5 } else { 1. },
@@ -898,7 +1532,10 @@ pub fn get_wrapper_node_bundle() -> NodeBundle {
     }
 }
 
-fn get_panel_tween(is_in: bool) -> Tween<Style> {
+fn get_panel_tween(is_in: bool, reduce_motion: bool) -> Tween<Style> {
+    // with reduced motion the panel just pops in/out instead of sliding
+    let (hidden_top, duration_ms) = if reduce_motion { (0., 50) } else { (100., 650) };
+
     Tween::new(
         if is_in {
             EaseFunction::CircularOut
@@ -906,20 +1543,58 @@ fn get_panel_tween(is_in: bool) -> Tween<Style> {
             EaseFunction::CircularIn
         },
         TweeningType::Once,
-        Duration::from_millis(650),
+        Duration::from_millis(duration_ms),
         UiPositionLens {
             start: Rect {
-                top: Val::Percent(if is_in { 100. } else { 0. }),
+                top: Val::Percent(if is_in { hidden_top } else { 0. }),
                 ..Default::default()
             },
             end: Rect {
-                top: Val::Percent(if is_in { 0. } else { 100. }),
+                top: Val::Percent(if is_in { 0. } else { hidden_top }),
                 ..Default::default()
             },
         },
     )
 }
 
+fn get_btn_wrapper_node(btn_style: &ButtonInteractionStyles, width: Val) -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            size: Size::new(width, Val::Px(50.)),
+            min_size: Size::new(Val::Px(150.0), Val::Px(40.0)),
+            position: btn_style.normal.position,
+            margin: Rect {
+                left: Val::Px(10.),
+                right: Val::Px(10.),
+                bottom: Val::Px(10.),
+                ..Default::default()
+            },
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..Default::default()
+        },
+        color: Color::NONE.into(),
+        ..Default::default()
+    }
+}
+
+fn get_btns_grid_wrapper_node() -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            size: Size::new(Val::Percent(100.), Val::Auto),
+            margin: Rect::all(Val::Auto),
+            flex_direction: FlexDirection::Row,
+            // ui y goes up, so reverse the wrap to fill the rows top to bottom
+            flex_wrap: FlexWrap::WrapReverse,
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..Default::default()
+        },
+        color: Color::NONE.into(),
+        ..Default::default()
+    }
+}
+
 fn get_btns_wrapper_node() -> NodeBundle {
     NodeBundle {
         style: Style {
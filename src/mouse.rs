@@ -1,39 +1,139 @@
-use crate::{game_state::UpdatePhase, render::MainCamera};
-use bevy::prelude::*;
+use crate::{
+    render::{GameDimensions, GameScaling},
+    schedule::{PhaseSystem, UpdatePhase},
+};
+use bevy::{prelude::*, window::WindowId};
 
 pub struct MousePlugin;
 impl Plugin for MousePlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<CursorWorldPosition>()
-            .add_system(store_cursor_pos.label(UpdatePhase::Input));
+        app.init_resource::<CursorWorldPosition>().add_system(
+            store_cursor_pos
+                .in_phase(UpdatePhase::Input)
+                .label(MouseSystem::StoreCursor),
+        );
     }
 }
 
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MouseSystem {
+    StoreCursor,
+}
+
+/// Marks cameras the cursor position gets projected through.
+/// With several cameras, the first one rendering to the window the cursor is in wins.
+#[derive(Component)]
+pub struct CursorCamera;
+
+/// `None` until the cursor has been over a window with a [`CursorCamera`]
 #[derive(Default)]
-pub struct CursorWorldPosition(pub Vec2);
+pub struct CursorWorldPosition(pub Option<CursorPosition>);
+
+#[derive(Debug, Clone, Copy)]
+pub struct CursorPosition {
+    pub world: Vec2,
+    /// Window position in pixels with the origin in the bottom left corner
+    pub screen: Vec2,
+    /// World-space movement since the previous frame
+    pub delta: Vec2,
+    /// The positions are the last known ones when the cursor has left the window
+    pub is_inside: bool,
+    pub window: WindowId,
+    pub camera: Entity,
+}
+
+impl CursorWorldPosition {
+    /// The position if the cursor is currently over a window
+    pub fn get_inside(&self) -> Option<&CursorPosition> {
+        self.0.as_ref().filter(|p| p.is_inside)
+    }
+
+    pub fn get_world_pos(&self) -> Option<Vec2> {
+        self.get_inside().map(|p| p.world)
+    }
+}
 
-#[allow(clippy::only_used_in_recursion)]
 fn store_cursor_pos(
     wnds: Res<Windows>,
-    q_camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    camera_q: Query<
+        (
+            Entity,
+            &Camera,
+            &GlobalTransform,
+            Option<&OrthographicProjection>,
+        ),
+        With<CursorCamera>,
+    >,
     mut cursor_pos: ResMut<CursorWorldPosition>,
+    scaling: Res<GameScaling>,
+    dimensions: Res<GameDimensions>,
 ) {
-    // get the camera info and transform
-    // assuming there is exactly one main camera entity, so query::single() is OK
-    let (camera, camera_transform) = q_camera.single();
-
-    let wnd = wnds.get(camera.window).unwrap();
-
-    // check if the cursor is inside the window and get its position
-    if let Some(screen_pos) = wnd.cursor_position() {
-        let window_size = Vec2::new(wnd.width() as f32, wnd.height() as f32);
-        // convert screen position [0..resolution] to ndc [-1..1] (gpu coordinates)
-        let ndc = (screen_pos / window_size) * 2.0 - Vec2::ONE;
-        // matrix for undoing the projection and camera transform
-        let ndc_to_world = camera_transform.compute_matrix() * camera.projection_matrix.inverse();
-        // use it to convert ndc to world-space coordinates
-        let world_pos = ndc_to_world.project_point3(ndc.extend(-1.0));
-
-        cursor_pos.0 = world_pos.truncate();
+    let hovered = camera_q
+        .iter()
+        .find_map(|(camera_e, camera, camera_t, projection)| {
+            let wnd = wnds.get(camera.window)?;
+            let screen_pos = wnd.cursor_position()?;
+            let window_size = Vec2::new(wnd.width() as f32, wnd.height() as f32);
+
+            if window_size.cmple(Vec2::ZERO).any() {
+                // minimized
+                return None;
+            }
+
+            let world_pos = get_world_pos(screen_pos, window_size, camera, camera_t);
+
+            // the letterbox bars count as outside of the window
+            let zoom = projection.map_or(1., |p| p.scale);
+
+            if scaling.has_letterbox()
+                && (world_pos - camera_t.translation.truncate())
+                    .abs()
+                    .cmpgt(dimensions.base_size * zoom * 0.5)
+                    .any()
+            {
+                return None;
+            }
+
+            Some((camera_e, camera.window, screen_pos, world_pos))
+        });
+
+    match hovered {
+        Some((camera_e, window, screen, world)) => {
+            let delta = match cursor_pos.0 {
+                Some(prev) if prev.is_inside && prev.window == window => world - prev.world,
+                _ => Vec2::ZERO,
+            };
+
+            cursor_pos.0 = Some(CursorPosition {
+                world,
+                screen,
+                delta,
+                is_inside: true,
+                window,
+                camera: camera_e,
+            });
+        }
+        None => {
+            if let Some(pos) = cursor_pos.0.as_mut() {
+                if pos.is_inside || pos.delta != Vec2::ZERO {
+                    pos.is_inside = false;
+                    pos.delta = Vec2::ZERO;
+                }
+            }
+        }
     }
 }
+
+pub fn get_world_pos(
+    screen_pos: Vec2,
+    window_size: Vec2,
+    camera: &Camera,
+    camera_t: &GlobalTransform,
+) -> Vec2 {
+    // convert screen position [0..resolution] to ndc [-1..1] (gpu coordinates)
+    let ndc = (screen_pos / window_size) * 2.0 - Vec2::ONE;
+    // matrix for undoing the projection and camera transform
+    let ndc_to_world = camera_t.compute_matrix() * camera.projection_matrix.inverse();
+    // use it to convert ndc to world-space coordinates
+    ndc_to_world.project_point3(ndc.extend(-1.0)).truncate()
+}
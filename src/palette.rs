@@ -1,36 +1,50 @@
+use crate::schedule::{PhaseSystem, UpdatePhase};
 use bevy::prelude::*;
 use bevy_prototype_lyon::{
     entity::ShapeBundle,
     prelude::{DrawMode, FillMode, GeometryBuilder, StrokeMode},
 };
+use serde::{Deserialize, Serialize};
 
 pub struct PalettePlugin;
 impl Plugin for PalettePlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.add_system(on_sprite_added)
-            .add_system(on_text_added)
-            .add_system(on_shape_palette_color_changed)
-            .add_system(on_ui_color_added)
-            .insert_resource(Palette {
-                background: Color::hsl(0., 0., 0.23),
-                text: Color::hsl(0., 0., 1.),
-                button: Color::hsl(0., 0., 0.45),
-                button_focus: Color::hsl(0., 0., 0.60),
-                button_active: Color::hsl(0., 0., 0.80),
-                button_accent: Color::rgb_u8(0, 68, 115),
-                button_accent_focus: Color::rgb_u8(0, 60, 150),
-                button_accent_active: Color::rgb_u8(54, 0, 162),
-                button_text: Color::hsl(0., 0., 0.75),
-                button_text_focus: Color::hsl(0., 0., 0.85),
-                button_text_active: Color::hsl(0., 0., 0.95),
-                button_text_accent: Color::hsl(0., 0., 0.75),
-                button_text_accent_focus: Color::hsl(0., 0., 0.85),
-                button_text_accent_active: Color::hsl(0., 0., 0.95),
-            });
+        app.add_system(on_sprite_added.in_phase(UpdatePhase::Animation))
+            .add_system(on_text_added.in_phase(UpdatePhase::Animation))
+            .add_system(on_shape_palette_color_changed.in_phase(UpdatePhase::Animation))
+            .add_system(on_ui_color_added.in_phase(UpdatePhase::Animation))
+            .add_system(on_palette_changed.in_phase(UpdatePhase::Animation))
+            .insert_resource(Palette::from_theme(PaletteTheme::default()));
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PaletteTheme {
+    #[default]
+    Dark,
+    Light,
+    HighContrast,
+}
+
+impl PaletteTheme {
+    pub const ALL: [PaletteTheme; 3] = [
+        PaletteTheme::Dark,
+        PaletteTheme::Light,
+        PaletteTheme::HighContrast,
+    ];
+    pub const NAMES: [&'static str; 3] = ["Dark", "Light", "High contrast"];
+
+    pub fn get_index(&self) -> usize {
+        Self::ALL.iter().position(|t| t == self).unwrap_or_default()
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        Self::NAMES[self.get_index()]
     }
 }
 
 pub struct Palette {
+    theme: PaletteTheme,
     background: Color,
     text: Color,
     button: Color,
@@ -48,6 +62,66 @@ pub struct Palette {
 }
 
 impl Palette {
+    pub fn from_theme(theme: PaletteTheme) -> Self {
+        match theme {
+            PaletteTheme::Dark => Palette {
+                theme,
+                background: Color::hsl(0., 0., 0.23),
+                text: Color::hsl(0., 0., 1.),
+                button: Color::hsl(0., 0., 0.45),
+                button_focus: Color::hsl(0., 0., 0.60),
+                button_active: Color::hsl(0., 0., 0.80),
+                button_accent: Color::rgb_u8(0, 68, 115),
+                button_accent_focus: Color::rgb_u8(0, 60, 150),
+                button_accent_active: Color::rgb_u8(54, 0, 162),
+                button_text: Color::hsl(0., 0., 0.75),
+                button_text_focus: Color::hsl(0., 0., 0.85),
+                button_text_active: Color::hsl(0., 0., 0.95),
+                button_text_accent: Color::hsl(0., 0., 0.75),
+                button_text_accent_focus: Color::hsl(0., 0., 0.85),
+                button_text_accent_active: Color::hsl(0., 0., 0.95),
+            },
+            PaletteTheme::Light => Palette {
+                theme,
+                background: Color::hsl(0., 0., 0.9),
+                text: Color::hsl(0., 0., 0.1),
+                button: Color::hsl(0., 0., 0.75),
+                button_focus: Color::hsl(0., 0., 0.62),
+                button_active: Color::hsl(0., 0., 0.45),
+                button_accent: Color::rgb_u8(120, 190, 235),
+                button_accent_focus: Color::rgb_u8(80, 160, 230),
+                button_accent_active: Color::rgb_u8(140, 100, 230),
+                button_text: Color::hsl(0., 0., 0.25),
+                button_text_focus: Color::hsl(0., 0., 0.15),
+                button_text_active: Color::hsl(0., 0., 0.05),
+                button_text_accent: Color::hsl(0., 0., 0.2),
+                button_text_accent_focus: Color::hsl(0., 0., 0.1),
+                button_text_accent_active: Color::hsl(0., 0., 0.),
+            },
+            PaletteTheme::HighContrast => Palette {
+                theme,
+                background: Color::BLACK,
+                text: Color::WHITE,
+                button: Color::hsl(0., 0., 0.1),
+                button_focus: Color::YELLOW,
+                button_active: Color::WHITE,
+                button_accent: Color::rgb_u8(0, 40, 120),
+                button_accent_focus: Color::YELLOW,
+                button_accent_active: Color::WHITE,
+                button_text: Color::WHITE,
+                button_text_focus: Color::BLACK,
+                button_text_active: Color::BLACK,
+                button_text_accent: Color::WHITE,
+                button_text_accent_focus: Color::BLACK,
+                button_text_accent_active: Color::BLACK,
+            },
+        }
+    }
+
+    pub fn get_theme(&self) -> PaletteTheme {
+        self.theme
+    }
+
     pub fn get_color(&self, col: &PaletteColor) -> Color {
         match col {
             PaletteColor::Background => self.background,
@@ -126,6 +200,35 @@ fn on_ui_color_added(
     }
 }
 
+fn on_palette_changed(
+    palette: Res<Palette>,
+    mut sprite_q: Query<(&PaletteColor, &mut Sprite)>,
+    mut text_q: Query<(&PaletteColor, &mut Text)>,
+    mut ui_col_q: Query<(&PaletteColor, &mut UiColor), Without<Text>>,
+    mut shape_q: Query<&mut ShapePaletteColor>,
+) {
+    if !palette.is_changed() || palette.is_added() {
+        return;
+    }
+
+    for (col, mut sprite) in sprite_q.iter_mut() {
+        sprite.color = palette.get_color(col);
+    }
+
+    for (col, mut text) in text_q.iter_mut() {
+        text.sections[0].style.color = palette.get_color(col);
+    }
+
+    for (col, mut ui_col) in ui_col_q.iter_mut() {
+        ui_col.0 = palette.get_color(col);
+    }
+
+    // retrigger the change detection so the draw mode gets rebuilt
+    for mut shape_col in shape_q.iter_mut() {
+        shape_col.set_changed();
+    }
+}
+
 fn on_shape_palette_color_changed(
     palette: Res<Palette>,
     mut q: Query<(&mut DrawMode, &ShapePaletteColor), Changed<ShapePaletteColor>>,
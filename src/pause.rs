@@ -1,33 +1,76 @@
-use bevy::prelude::*;
+use crate::{
+    game_state::GameStateStack,
+    input_binding::{get_devices_just_pressed, BindingTarget, UiAction},
+    schedule::{PhaseSystem, UpdatePhase},
+    settings::Settings,
+    GameState,
+};
+use bevy::{prelude::*, window::WindowFocused};
 use bevy_time::*;
 
 pub struct PausePlugin;
 impl Plugin for PausePlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.add_system(process_inactive);
+        app.init_resource::<PausedTimeScale>()
+            .add_system(pause_on_focus_lost.in_phase(UpdatePhase::Logic))
+            .add_system_set(
+                SystemSet::on_update(GameState::Game)
+                    .with_system(handle_pause_input.in_phase(UpdatePhase::Logic)),
+            )
+            // any overlay pauses the game, e.g. the disconnected one too
+            .add_system_set(
+                SystemSet::on_pause(GameState::Game)
+                    .with_system(stop_time.in_phase(UpdatePhase::Logic)),
+            )
+            .add_system_set(
+                SystemSet::on_resume(GameState::Game)
+                    .with_system(restore_time.in_phase(UpdatePhase::Logic)),
+            );
     }
 }
 
-#[derive(Component)]
-pub enum Inactive {
-    Timed { timer: Timer },
-    Permanent,
+/// The scale from before pausing, so slow-mo etc. survives a pause.
+/// Only the first overlay saves it, so stacked overlays can't save the stopped scale.
+#[derive(Default)]
+struct PausedTimeScale(Option<f32>);
+
+fn handle_pause_input(
+    mut state: ResMut<State<GameState>>,
+    settings: Res<Settings>,
+    keys: Res<Input<KeyCode>>,
+    gamepad_btns: Res<Input<GamepadButton>>,
+) {
+    let cancel_devices = get_devices_just_pressed(
+        BindingTarget::Ui(UiAction::Cancel),
+        &settings.bindings,
+        &keys,
+        &gamepad_btns,
+    );
+
+    if !cancel_devices.is_empty() {
+        state.open(GameState::Paused).unwrap();
+    }
 }
 
-fn process_inactive(
-    mut commands: Commands,
-    mut inactive_q: Query<(Entity, &mut Inactive)>,
-    time: ScaledTime,
+fn pause_on_focus_lost(
+    mut focus_evr: EventReader<WindowFocused>,
+    mut state: ResMut<State<GameState>>,
 ) {
-    for (inactive_e, mut inactive) in inactive_q.iter_mut() {
-        match &mut *inactive {
-            Inactive::Timed { timer } => {
-                timer.tick(time.scaled_delta());
-                if timer.just_finished() {
-                    commands.entity(inactive_e).remove::<Inactive>();
-                }
-            }
-            Inactive::Permanent => {}
-        }
+    let is_unfocused = focus_evr.iter().any(|ev| ev.id.is_primary() && !ev.focused);
+
+    if is_unfocused && *state.current() == GameState::Game {
+        state.open(GameState::Paused).unwrap();
     }
 }
+
+fn stop_time(mut time_scale: ResMut<TimeScale>, mut paused_scale: ResMut<PausedTimeScale>) {
+    if paused_scale.0.is_none() {
+        paused_scale.0 = Some(time_scale.0);
+    }
+
+    time_scale.0 = 0.;
+}
+
+fn restore_time(mut time_scale: ResMut<TimeScale>, mut paused_scale: ResMut<PausedTimeScale>) {
+    time_scale.0 = paused_scale.0.take().unwrap_or(1.);
+}
@@ -0,0 +1,315 @@
+use crate::{
+    mouse::CursorWorldPosition,
+    schedule::{PhaseSystem, UpdatePhase},
+};
+use bevy::{
+    prelude::*,
+    render::mesh::{Indices, VertexAttributeValues},
+    sprite::Mesh2dHandle,
+};
+use heron::CollisionShape;
+
+pub struct PickingPlugin;
+impl Plugin for PickingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PickingState>()
+            .add_event::<PointerEnter>()
+            .add_event::<PointerExit>()
+            .add_event::<Click>()
+            .add_event::<DragStart>()
+            .add_event::<Drag>()
+            .add_event::<DragEnd>()
+            .add_system(update_picking.in_phase(UpdatePhase::Logic));
+    }
+}
+
+/// Cursor distance in world units a press has to move to start a drag
+const DRAG_THRESHOLD: f32 = 4.;
+
+/// Opt-in marker for entities which should receive pointer events.
+/// Entities are hit-tested against their sprite, collider or shape mesh, in that order.
+#[derive(Component, Default)]
+pub struct Pickable;
+
+pub struct PointerEnter {
+    pub entity: Entity,
+}
+
+pub struct PointerExit {
+    pub entity: Entity,
+}
+
+/// Pressed and released on the same entity without dragging
+pub struct Click {
+    pub entity: Entity,
+}
+
+pub struct DragStart {
+    pub entity: Entity,
+    pub world_pos: Vec2,
+}
+
+pub struct Drag {
+    pub entity: Entity,
+    pub world_pos: Vec2,
+    pub delta: Vec2,
+}
+
+pub struct DragEnd {
+    pub entity: Entity,
+    pub world_pos: Vec2,
+}
+
+#[derive(Default)]
+pub struct PickingState {
+    hovered: Option<Entity>,
+    pressed: Option<PickPress>,
+}
+
+struct PickPress {
+    entity: Entity,
+    start_pos: Vec2,
+    last_pos: Vec2,
+    is_dragging: bool,
+}
+
+impl PickingState {
+    pub fn get_hovered(&self) -> Option<Entity> {
+        self.hovered
+    }
+
+    pub fn get_dragged(&self) -> Option<Entity> {
+        self.pressed
+            .as_ref()
+            .filter(|p| p.is_dragging)
+            .map(|p| p.entity)
+    }
+}
+
+fn update_picking(
+    mut state: ResMut<PickingState>,
+    cursor: Res<CursorWorldPosition>,
+    mouse_btns: Res<Input<MouseButton>>,
+    pickable_q: Query<
+        (
+            Entity,
+            &GlobalTransform,
+            Option<&Sprite>,
+            Option<&Handle<Image>>,
+            Option<&CollisionShape>,
+            Option<&Mesh2dHandle>,
+        ),
+        With<Pickable>,
+    >,
+    ui_q: Query<&Interaction>,
+    images: Res<Assets<Image>>,
+    meshes: Res<Assets<Mesh>>,
+    mut enter_evw: EventWriter<PointerEnter>,
+    mut exit_evw: EventWriter<PointerExit>,
+    mut click_evw: EventWriter<Click>,
+    mut drag_start_evw: EventWriter<DragStart>,
+    mut drag_evw: EventWriter<Drag>,
+    mut drag_end_evw: EventWriter<DragEnd>,
+) {
+    let cursor_pos = cursor.get_world_pos();
+    // the ui is drawn above the world, so it blocks picking
+    let is_over_ui = ui_q.iter().any(|i| *i != Interaction::None);
+
+    // the topmost hit wins
+    let hit = match cursor_pos {
+        Some(pos) if !is_over_ui => pickable_q
+            .iter()
+            .filter(|(_, t, sprite, img, collider, mesh)| {
+                let local_pos = t
+                    .compute_matrix()
+                    .inverse()
+                    .transform_point3(pos.extend(0.));
+                is_hit(
+                    local_pos.truncate(),
+                    *sprite,
+                    *img,
+                    *collider,
+                    *mesh,
+                    &images,
+                    &meshes,
+                )
+            })
+            .max_by(|(_, a, ..), (_, b, ..)| a.translation.z.total_cmp(&b.translation.z))
+            .map(|(e, ..)| e),
+        _ => None,
+    };
+
+    // hover stays on the dragged entity even if the cursor moves off of it
+    let hovered = match state.get_dragged() {
+        Some(dragged_e) => Some(dragged_e),
+        None => hit,
+    };
+
+    if hovered != state.hovered {
+        if let Some(prev_e) = state.hovered {
+            if pickable_q.get(prev_e).is_ok() {
+                exit_evw.send(PointerExit { entity: prev_e });
+            }
+        }
+
+        if let Some(e) = hovered {
+            enter_evw.send(PointerEnter { entity: e });
+        }
+
+        state.hovered = hovered;
+    }
+
+    if mouse_btns.just_pressed(MouseButton::Left) {
+        state.pressed = hit.zip(cursor_pos).map(|(entity, pos)| PickPress {
+            entity,
+            start_pos: pos,
+            last_pos: pos,
+            is_dragging: false,
+        });
+    }
+
+    let is_released = mouse_btns.just_released(MouseButton::Left);
+
+    if let Some(press) = state.pressed.as_mut() {
+        // the pressed entity might've been despawned
+        if pickable_q.get(press.entity).is_err() {
+            state.pressed = None;
+            return;
+        }
+
+        // a drag outside of the window continues from the last known position
+        let pos = cursor_pos.unwrap_or(press.last_pos);
+
+        if !press.is_dragging && press.start_pos.distance(pos) >= DRAG_THRESHOLD {
+            press.is_dragging = true;
+            drag_start_evw.send(DragStart {
+                entity: press.entity,
+                world_pos: press.start_pos,
+            });
+        }
+
+        if press.is_dragging && press.last_pos != pos {
+            drag_evw.send(Drag {
+                entity: press.entity,
+                world_pos: pos,
+                delta: pos - press.last_pos,
+            });
+        }
+
+        press.last_pos = pos;
+
+        if is_released {
+            if press.is_dragging {
+                drag_end_evw.send(DragEnd {
+                    entity: press.entity,
+                    world_pos: pos,
+                });
+            } else if hit == Some(press.entity) {
+                click_evw.send(Click {
+                    entity: press.entity,
+                });
+            }
+
+            state.pressed = None;
+        }
+    }
+}
+
+fn is_hit(
+    local_pos: Vec2,
+    sprite: Option<&Sprite>,
+    img: Option<&Handle<Image>>,
+    collider: Option<&CollisionShape>,
+    mesh: Option<&Mesh2dHandle>,
+    images: &Assets<Image>,
+    meshes: &Assets<Mesh>,
+) -> bool {
+    if let Some(sprite) = sprite {
+        let size = sprite.custom_size.or_else(|| {
+            img.and_then(|h| images.get(h)).map(|img| {
+                let size = img.texture_descriptor.size;
+                Vec2::new(size.width as f32, size.height as f32)
+            })
+        });
+
+        if let Some(size) = size {
+            return local_pos.abs().cmple(size * 0.5).all();
+        }
+    }
+
+    if let Some(collider) = collider {
+        if let Some(is_hit) = is_collider_hit(local_pos, collider) {
+            return is_hit;
+        }
+    }
+
+    if let Some(mesh) = mesh.and_then(|h| meshes.get(&h.0)) {
+        return is_mesh_hit(local_pos, mesh);
+    }
+
+    false
+}
+
+/// `None` for shapes which can't be hit-tested in 2d
+fn is_collider_hit(local_pos: Vec2, collider: &CollisionShape) -> Option<bool> {
+    match collider {
+        CollisionShape::Sphere { radius } => Some(local_pos.length() <= *radius),
+        CollisionShape::Cuboid {
+            half_extends,
+            border_radius,
+        } => {
+            let half_extends = half_extends.truncate() + Vec2::splat(border_radius.unwrap_or(0.));
+            Some(local_pos.abs().cmple(half_extends).all())
+        }
+        CollisionShape::Capsule {
+            half_segment,
+            radius,
+        } => {
+            // heron capsules are aligned with the y axis
+            let closest = Vec2::new(0., local_pos.y.clamp(-half_segment, *half_segment));
+            Some(local_pos.distance(closest) <= *radius)
+        }
+        _ => None,
+    }
+}
+
+/// Point-in-triangle test against the tessellated mesh (lyon shapes)
+fn is_mesh_hit(local_pos: Vec2, mesh: &Mesh) -> bool {
+    let positions: Vec<Vec2> = match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+        Some(VertexAttributeValues::Float32x3(positions)) => {
+            positions.iter().map(|p| Vec2::new(p[0], p[1])).collect()
+        }
+        Some(VertexAttributeValues::Float32x2(positions)) => {
+            positions.iter().map(|p| Vec2::new(p[0], p[1])).collect()
+        }
+        _ => return false,
+    };
+
+    let indices: Vec<usize> = match mesh.indices() {
+        Some(Indices::U16(indices)) => indices.iter().map(|i| *i as usize).collect(),
+        Some(Indices::U32(indices)) => indices.iter().map(|i| *i as usize).collect(),
+        None => (0..positions.len()).collect(),
+    };
+
+    indices.chunks_exact(3).any(|tri| {
+        match (
+            positions.get(tri[0]),
+            positions.get(tri[1]),
+            positions.get(tri[2]),
+        ) {
+            (Some(a), Some(b), Some(c)) => is_in_triangle(local_pos, *a, *b, *c),
+            _ => false,
+        }
+    })
+}
+
+fn is_in_triangle(p: Vec2, a: Vec2, b: Vec2, c: Vec2) -> bool {
+    let sign = |p1: Vec2, p2: Vec2, p3: Vec2| (p1 - p3).perp_dot(p2 - p3);
+    let d1 = sign(p, a, b);
+    let d2 = sign(p, b, c);
+    let d3 = sign(p, c, a);
+    let has_neg = d1 < 0. || d2 < 0. || d3 < 0.;
+    let has_pos = d1 > 0. || d2 > 0. || d3 > 0.;
+
+    !(has_neg && has_pos)
+}
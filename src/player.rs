@@ -0,0 +1,188 @@
+use crate::{
+    camera::CameraTarget,
+    input_binding::{get_player_input_map, InputBindings, PlayerActionMap, PlayerDevice},
+    input_buffer::InputBuffer,
+    palette::{Palette, PaletteColor},
+    schedule::{PhaseSystem, UpdatePhase},
+    settings::Settings,
+    tutorial::{TutorialStepId, TutorialTarget},
+    GameState,
+};
+use bevy::{ecs::system::EntityCommands, prelude::*};
+
+pub struct PlayerPlugin;
+impl Plugin for PlayerPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<PlayerSlots>()
+            .add_system_set(
+                SystemSet::on_enter(GameState::Game)
+                    .with_system(spawn_players.in_phase(UpdatePhase::Logic)),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Game)
+                    .with_system(despawn_players.in_phase(UpdatePhase::Logic)),
+            )
+            .add_system(refresh_player_input_maps.in_phase(UpdatePhase::Input));
+    }
+}
+
+pub const MAX_PLAYERS: usize = 4;
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Player {
+    pub id: usize,
+    pub device: PlayerDevice,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PlayerSlot {
+    pub device: PlayerDevice,
+    pub connected: bool,
+}
+
+/// Devices assigned to players in the lobby, indexed by player id
+#[derive(Default)]
+pub struct PlayerSlots(pub [Option<PlayerSlot>; MAX_PLAYERS]);
+
+impl PlayerSlots {
+    /// Assigns the device to the first free slot and returns its id
+    pub fn join(&mut self, device: PlayerDevice) -> Option<usize> {
+        if self.get_id(device).is_some() {
+            return None;
+        }
+
+        let id = self.0.iter().position(|s| s.is_none())?;
+        self.0[id] = Some(PlayerSlot {
+            device,
+            connected: true,
+        });
+
+        Some(id)
+    }
+
+    pub fn leave(&mut self, device: PlayerDevice) -> Option<usize> {
+        let id = self.get_id(device)?;
+        self.0[id] = None;
+        Some(id)
+    }
+
+    pub fn get_id(&self, device: PlayerDevice) -> Option<usize> {
+        self.0
+            .iter()
+            .position(|s| matches!(s, Some(slot) if slot.device == device))
+    }
+
+    pub fn set_connected(&mut self, device: PlayerDevice, connected: bool) -> Option<usize> {
+        let id = self.get_id(device)?;
+        if let Some(slot) = self.0[id].as_mut() {
+            slot.connected = connected;
+        }
+
+        Some(id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &PlayerSlot)> {
+        self.0
+            .iter()
+            .enumerate()
+            .filter_map(|(id, s)| s.as_ref().map(|s| (id, s)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    pub fn all_connected(&self) -> bool {
+        self.iter().all(|(_, slot)| slot.connected)
+    }
+}
+
+/// Attaches the player marker, an input buffer and the input map built from the current bindings.
+/// `ActionInput` is added by the input plugin alongside the map.
+pub fn spawn_player<'w, 's, 'a>(
+    commands: &'a mut Commands<'w, 's>,
+    id: usize,
+    device: PlayerDevice,
+    settings: &Settings,
+) -> EntityCommands<'w, 's, 'a> {
+    let mut player_cmd = commands.spawn();
+    player_cmd
+        .insert(Player { id, device })
+        .insert(InputBuffer::default());
+
+    match get_player_input_map(device, &settings.bindings) {
+        Ok(map) => {
+            player_cmd.insert(map);
+        }
+        Err(err) => warn!("Invalid player {id} bindings: {err:?}"),
+    }
+
+    player_cmd
+}
+
+fn spawn_players(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    palette: Res<Palette>,
+    mut slots: ResMut<PlayerSlots>,
+    player_q: Query<(), With<Player>>,
+) {
+    // players might've been spawned before entering the game
+    if !player_q.is_empty() {
+        return;
+    }
+
+    // the game can be started without going through the lobby
+    if slots.is_empty() {
+        slots.join(PlayerDevice::Keyboard);
+    }
+
+    let player_count = slots.iter().count();
+
+    for (i, (id, slot)) in slots.iter().enumerate() {
+        let x = (i as f32 - (player_count - 1) as f32 * 0.5) * 80.;
+
+        spawn_player(&mut commands, id, slot.device, &settings)
+            .insert_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: palette.get_color(&PaletteColor::Text),
+                    custom_size: Some(Vec2::splat(40.)),
+                    ..Default::default()
+                },
+                transform: Transform::from_xyz(x, 0., 0.),
+                ..Default::default()
+            })
+            .insert(PaletteColor::Text)
+            .insert(CameraTarget::default())
+            .insert(TutorialTarget(TutorialStepId::Reset));
+    }
+}
+
+fn despawn_players(mut commands: Commands, player_q: Query<Entity, With<Player>>) {
+    for e in player_q.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
+
+fn refresh_player_input_maps(
+    settings: Res<Settings>,
+    mut player_q: Query<(&Player, &mut PlayerActionMap)>,
+    mut bindings: Local<Option<InputBindings>>,
+) {
+    // other settings change every frame of e.g. a slider drag
+    if !settings.is_changed() || bindings.as_ref() == Some(&settings.bindings) {
+        return;
+    }
+
+    // the maps are built from the bindings when the players spawn
+    if bindings.replace(settings.bindings.clone()).is_none() {
+        return;
+    }
+
+    for (player, mut map) in player_q.iter_mut() {
+        match get_player_input_map(player.device, &settings.bindings) {
+            Ok(new_map) => *map = new_map,
+            Err(err) => warn!("Invalid player {} bindings: {err:?}", player.id),
+        }
+    }
+}
@@ -1,10 +1,21 @@
-use bevy::{prelude::*, render::render_resource::FilterMode};
+use crate::{
+    camera::CameraController,
+    mouse::CursorCamera,
+    reset::Persistent,
+    schedule::{PhaseSystem, UpdatePhase},
+};
+use bevy::{prelude::*, render::camera::ScalingMode, window::WindowResized};
 
 pub struct RenderPlugin;
 impl Plugin for RenderPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(setup)
-            .add_system(set_img_sampler_filter);
+        app.init_resource::<GameScaling>()
+            .add_startup_system(setup)
+            .add_system(scale_camera.in_phase(UpdatePhase::Render))
+            .add_system(update_letterbox.in_phase(UpdatePhase::Render));
+
+        #[cfg(target_arch = "wasm32")]
+        app.add_system(fit_canvas_to_browser.in_phase(UpdatePhase::Render));
     }
 }
 
@@ -13,29 +24,190 @@ pub struct GameDimensions {
     pub min_size: Vec2,
 }
 
+/// How the `base_size` world area is mapped to the window
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GameScaling {
+    /// Whole base area is visible, the rest is covered by letterbox bars
+    #[default]
+    Fit,
+    /// Base area covers the whole window, the overflowing part is cropped
+    Fill,
+    /// Base area is stretched to the window, ignoring the aspect ratio
+    Stretch,
+    /// Like `Fit`, but only scales by whole numbers so pixels stay crisp.
+    /// Windows smaller than the base area scale down by whole fractions (1/2, 1/3...) instead.
+    IntegerPixelPerfect,
+}
+
+impl GameScaling {
+    pub fn has_letterbox(&self) -> bool {
+        matches!(self, GameScaling::Fit | GameScaling::IntegerPixelPerfect)
+    }
+
+    /// World size visible in a window of the given size
+    pub fn get_visible_size(&self, base_size: Vec2, window_size: Vec2) -> Vec2 {
+        let ratio = window_size / base_size;
+        let scale = match self {
+            GameScaling::Fit => ratio.min_element(),
+            GameScaling::Fill => ratio.max_element(),
+            GameScaling::Stretch => return base_size,
+            GameScaling::IntegerPixelPerfect => {
+                let ratio = ratio.min_element();
+
+                if ratio >= 1. {
+                    ratio.floor()
+                } else {
+                    1. / (1. / ratio).ceil()
+                }
+            }
+        };
+
+        window_size / scale
+    }
+}
+
 #[derive(Component)]
 pub struct MainCamera;
 
+#[derive(Component)]
+struct LetterboxBar {
+    side: Vec2,
+}
+
 fn setup(mut cmd: Commands) {
+    let mut camera = OrthographicCameraBundle::new_2d();
+    // the extents are set by scale_camera
+    camera.orthographic_projection.scaling_mode = ScalingMode::None;
+
     cmd.spawn()
-        .insert_bundle(OrthographicCameraBundle::new_2d())
-        .insert(MainCamera);
+        .insert_bundle(camera)
+        .insert(MainCamera)
+        .insert(CameraController::default())
+        .insert(CursorCamera)
+        .insert(Persistent)
+        .with_children(|b| {
+            for side in [Vec2::X, -Vec2::X, Vec2::Y, -Vec2::Y] {
+                b.spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::BLACK,
+                        ..Default::default()
+                    },
+                    // just in front of the camera, so it's drawn over everything
+                    transform: Transform::from_xyz(0., 0., -1.),
+                    visibility: Visibility { is_visible: false },
+                    ..Default::default()
+                })
+                .insert(LetterboxBar { side });
+            }
+        });
 }
 
-fn set_img_sampler_filter(
-    mut ev_asset: EventReader<AssetEvent<Image>>,
-    mut assets: ResMut<Assets<Image>>,
+fn scale_camera(
+    mut resize_evr: EventReader<WindowResized>,
+    wnds: Res<Windows>,
+    scaling: Res<GameScaling>,
+    dimensions: Res<GameDimensions>,
+    mut projection_q: Query<(&Camera, &mut OrthographicProjection), With<MainCamera>>,
+    added_q: Query<(), Added<MainCamera>>,
 ) {
-    for ev in ev_asset.iter() {
-        match ev {
-            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
-                if let Some(mut texture) = assets.get_mut(handle) {
-                    // set sampler filtering to add some AA (quite fuzzy though)
-                    texture.sampler_descriptor.mag_filter = FilterMode::Linear;
-                    texture.sampler_descriptor.min_filter = FilterMode::Linear;
-                }
+    let is_resized = resize_evr.iter().next().is_some();
+
+    if !is_resized && !scaling.is_changed() && !dimensions.is_changed() && added_q.is_empty() {
+        return;
+    }
+
+    for (camera, mut projection) in projection_q.iter_mut() {
+        if let Some(wnd) = wnds.get(camera.window) {
+            let window_size = Vec2::new(wnd.width(), wnd.height());
+
+            if window_size.cmple(Vec2::ZERO).any() {
+                // minimized
+                continue;
             }
-            _ => {}
+
+            let half_size = scaling.get_visible_size(dimensions.base_size, window_size) * 0.5;
+            projection.left = -half_size.x;
+            projection.right = half_size.x;
+            projection.bottom = -half_size.y;
+            projection.top = half_size.y;
         }
     }
 }
+
+fn update_letterbox(
+    scaling: Res<GameScaling>,
+    dimensions: Res<GameDimensions>,
+    projection_q: Query<
+        &OrthographicProjection,
+        (With<MainCamera>, Changed<OrthographicProjection>),
+    >,
+    mut bar_q: Query<(&LetterboxBar, &mut Sprite, &mut Transform, &mut Visibility)>,
+) {
+    let projection = match projection_q.iter().next() {
+        Some(projection) => projection,
+        None => return,
+    };
+
+    let visible_size = Vec2::new(
+        projection.right - projection.left,
+        projection.top - projection.bottom,
+    ) * projection.scale;
+    // the base area shrinks on screen when zooming out
+    let base_size = dimensions.base_size * projection.scale;
+
+    for (bar, mut sprite, mut t, mut visibility) in bar_q.iter_mut() {
+        // bars cover the space between the base area and the visible edge on their side
+        let overflow = ((visible_size - base_size) * 0.5 * bar.side.abs()).max_element();
+        visibility.is_visible = scaling.has_letterbox() && overflow > 0.;
+
+        if !visibility.is_visible {
+            continue;
+        }
+
+        let size = if bar.side.x != 0. {
+            Vec2::new(overflow, visible_size.y)
+        } else {
+            Vec2::new(visible_size.x, overflow)
+        };
+
+        sprite.custom_size = Some(size);
+        t.translation = (bar.side * (base_size + size) * 0.5).extend(t.translation.z);
+    }
+}
+
+/// The canvas doesn't follow the browser window on its own
+#[cfg(target_arch = "wasm32")]
+fn fit_canvas_to_browser(mut wnds: ResMut<Windows>) {
+    let browser_size = web_sys::window().and_then(|browser_wnd| {
+        Some(Vec2::new(
+            browser_wnd.inner_width().ok()?.as_f64()? as f32,
+            browser_wnd.inner_height().ok()?.as_f64()? as f32,
+        ))
+    });
+
+    if let (Some(size), Some(wnd)) = (browser_size, wnds.get_primary_mut()) {
+        if (wnd.width() - size.x).abs() > 0.5 || (wnd.height() - size.y).abs() > 0.5 {
+            wnd.set_resolution(size.x, size.y);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integer_scaling_keeps_base_size_visible() {
+        let base_size = Vec2::new(640., 360.);
+        let scaling = GameScaling::IntegerPixelPerfect;
+
+        assert_eq!(
+            scaling.get_visible_size(base_size, Vec2::new(1400., 800.)),
+            Vec2::new(700., 400.)
+        );
+        // smaller than the base size scales by 1/2
+        let visible = scaling.get_visible_size(base_size, Vec2::new(500., 300.));
+        assert_eq!(visible, Vec2::new(1000., 600.));
+        assert!(visible.cmpge(base_size).all());
+    }
+}
@@ -0,0 +1,551 @@
+// input recording/replay for reproducing playtester bugs
+// run with `--record` to save the session (every few seconds, so crashes keep most of it) and with `--replay <file>` to play it back
+
+use crate::{
+    rng::GameRng,
+    schedule::{PhaseSystem, UpdatePhase},
+    storage,
+    touch::{TouchSystem, TouchTapEvt, VirtualStick, VIRTUAL_STICK_GAMEPAD},
+};
+use bevy::{
+    app::AppExit,
+    input::{gamepad::GamepadEventType, InputSystem},
+    prelude::*,
+    ui::UiSystem,
+    utils::HashMap,
+    window::{WindowFocused, WindowId},
+};
+use bevy_time::*;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+pub struct ReplayPlugin;
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        let mode = match app.world.remove_resource::<ReplayMode>() {
+            Some(mode) => mode,
+            None => get_replay_mode_from_args(),
+        };
+
+        match mode {
+            ReplayMode::Off => {}
+            ReplayMode::Record => {
+                let seed = rand::random();
+                info!("recording input with seed {seed}");
+
+                app.insert_resource(GameRng::from_seed(seed))
+                    .insert_resource(Recorder {
+                        recording: Recording {
+                            version: RECORDING_VERSION,
+                            seed,
+                            frames: Vec::new(),
+                        },
+                        axes: HashMap::default(),
+                        flush_timer: Timer::from_seconds(RECORDING_FLUSH_SEC, true),
+                    })
+                    .add_system_to_stage(CoreStage::PreUpdate, record_frame.after(InputSystem))
+                    .add_system(
+                        record_touch
+                            .in_phase(UpdatePhase::Input)
+                            .after(TouchSystem::Track),
+                    )
+                    .add_system_to_stage(CoreStage::Last, save_recording);
+            }
+            ReplayMode::Replay(recording) => {
+                info!(
+                    "replaying {} frames with seed {}",
+                    recording.frames.len(),
+                    recording.seed
+                );
+
+                app.insert_resource(GameRng::from_seed(recording.seed))
+                    .insert_resource(Replayer {
+                        recording,
+                        frame: 0,
+                        keys: Input::default(),
+                        gamepad_btns: Input::default(),
+                        mouse_btns: Input::default(),
+                    })
+                    .add_system_to_stage(CoreStage::First, replay_events)
+                    .add_system_to_stage(
+                        CoreStage::PreUpdate,
+                        // ui interactions read the mouse and the cursor
+                        replay_frame.after(InputSystem).before(UiSystem::Focus),
+                    )
+                    .add_system(
+                        replay_touch
+                            .in_phase(UpdatePhase::Input)
+                            .after(TouchSystem::Track),
+                    );
+            }
+        }
+    }
+}
+
+const RECORDING_KEY: &str = "replay";
+const RECORDING_VERSION: u32 = 1;
+/// Real time between saves of the recording
+const RECORDING_FLUSH_SEC: f32 = 5.;
+const RECORDED_AXES: [GamepadAxisType; 8] = [
+    GamepadAxisType::LeftStickX,
+    GamepadAxisType::LeftStickY,
+    GamepadAxisType::LeftZ,
+    GamepadAxisType::RightStickX,
+    GamepadAxisType::RightStickY,
+    GamepadAxisType::RightZ,
+    GamepadAxisType::DPadX,
+    GamepadAxisType::DPadY,
+];
+
+/// Insert before adding [`ReplayPlugin`] to skip reading the mode from the command line args
+pub enum ReplayMode {
+    Off,
+    Record,
+    Replay(Recording),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Recording {
+    pub version: u32,
+    pub seed: u64,
+    pub frames: Vec<RecordedFrame>,
+}
+
+/// Raw device input of a single frame.
+/// `PlayerInput` and `UiInput` are derived from it through the bindings,
+/// so feeding it back reproduces their state as well.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    /// Scaled delta the frame was recorded with
+    pub delta_sec: f32,
+    pub keys_pressed: Vec<KeyCode>,
+    pub keys_released: Vec<KeyCode>,
+    pub gamepad_btns_pressed: Vec<GamepadButton>,
+    pub gamepad_btns_released: Vec<GamepadButton>,
+    /// Only axes which changed since the previous frame
+    pub gamepad_axes: Vec<(GamepadAxis, f32)>,
+    pub gamepads_connected: Vec<Gamepad>,
+    pub gamepads_disconnected: Vec<Gamepad>,
+    /// The touch stick is written during the update, so it's recorded and replayed right after that
+    #[serde(default)]
+    pub virtual_stick: Vec2,
+    #[serde(default)]
+    pub mouse_btns_pressed: Vec<MouseButton>,
+    #[serde(default)]
+    pub mouse_btns_released: Vec<MouseButton>,
+    /// Primary window cursor in pixels with the origin in the bottom left corner
+    #[serde(default)]
+    pub cursor_pos: Option<Vec2>,
+    /// Primary window focus changes
+    #[serde(default)]
+    pub window_focus: Vec<bool>,
+    /// Raw touches aren't recorded, so touch input only replays through the taps and the virtual stick
+    #[serde(default)]
+    pub touch_taps: Vec<Vec2>,
+}
+
+pub struct Recorder {
+    recording: Recording,
+    axes: HashMap<GamepadAxis, f32>,
+    flush_timer: Timer,
+}
+
+impl Recorder {
+    pub fn get_recording(&self) -> &Recording {
+        &self.recording
+    }
+}
+
+pub struct Replayer {
+    recording: Recording,
+    frame: usize,
+    keys: Input<KeyCode>,
+    gamepad_btns: Input<GamepadButton>,
+    mouse_btns: Input<MouseButton>,
+}
+
+impl Replayer {
+    /// Recorded delta of the current frame
+    pub fn get_frame_delta(&self) -> Option<f32> {
+        self.get_current_frame().map(|f| f.delta_sec)
+    }
+
+    fn get_current_frame(&self) -> Option<&RecordedFrame> {
+        self.recording.frames.get(self.frame.checked_sub(1)?)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.recording.frames.len()
+    }
+}
+
+/// Scaled delta of the frame, the recorded one during a replay.
+/// Use it instead of the scaled delta in anything which has to play out the same in a replay.
+/// Bevy's `Time` can't be overridden, so real time ui timings still follow the real frame.
+pub fn get_scaled_delta(time: &ScaledTime, replayer: Option<&Replayer>) -> Duration {
+    replayer
+        .and_then(|r| r.get_frame_delta())
+        .map_or_else(|| time.scaled_delta(), Duration::from_secs_f32)
+}
+
+fn get_replay_mode_from_args() -> ReplayMode {
+    let args: Vec<String> = std::env::args().collect();
+
+    if args.iter().any(|a| a == "--record") {
+        return ReplayMode::Record;
+    }
+
+    if let Some(path) = args
+        .iter()
+        .position(|a| a == "--replay")
+        .and_then(|i| args.get(i + 1))
+    {
+        match load_recording(path) {
+            Ok(recording) => return ReplayMode::Replay(recording),
+            Err(err) => warn!("Failed to load replay {path}: {err}"),
+        }
+    }
+
+    ReplayMode::Off
+}
+
+fn load_recording(path: &str) -> Result<Recording, String> {
+    let data = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let recording: Recording = ron::from_str(&data).map_err(|e| e.to_string())?;
+
+    if recording.version != RECORDING_VERSION {
+        return Err(format!("unsupported version {}", recording.version));
+    }
+
+    Ok(recording)
+}
+
+fn record_frame(
+    mut recorder: ResMut<Recorder>,
+    keys: Res<Input<KeyCode>>,
+    gamepad_btns: Res<Input<GamepadButton>>,
+    mouse_btns: Res<Input<MouseButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    gamepads: Res<Gamepads>,
+    wnds: Res<Windows>,
+    mut gamepad_evr: EventReader<GamepadEvent>,
+    mut focus_evr: EventReader<WindowFocused>,
+    time: ScaledTime,
+) {
+    let mut frame = RecordedFrame {
+        delta_sec: time.scaled_delta_seconds(),
+        keys_pressed: keys.get_just_pressed().copied().collect(),
+        keys_released: keys.get_just_released().copied().collect(),
+        gamepad_btns_pressed: gamepad_btns.get_just_pressed().copied().collect(),
+        gamepad_btns_released: gamepad_btns.get_just_released().copied().collect(),
+        mouse_btns_pressed: mouse_btns.get_just_pressed().copied().collect(),
+        mouse_btns_released: mouse_btns.get_just_released().copied().collect(),
+        cursor_pos: wnds.get_primary().and_then(|w| w.cursor_position()),
+        window_focus: focus_evr
+            .iter()
+            .filter(|ev| ev.id.is_primary())
+            .map(|ev| ev.focused)
+            .collect(),
+        ..Default::default()
+    };
+
+    for GamepadEvent(gamepad, ev_type) in gamepad_evr.iter() {
+        match ev_type {
+            GamepadEventType::Connected => frame.gamepads_connected.push(*gamepad),
+            GamepadEventType::Disconnected => frame.gamepads_disconnected.push(*gamepad),
+            _ => {}
+        }
+    }
+
+    for gamepad in gamepads.iter() {
+        for axis_type in RECORDED_AXES {
+            let axis = GamepadAxis(*gamepad, axis_type);
+            let val = axes.get(axis).unwrap_or(0.);
+
+            if recorder.axes.get(&axis) != Some(&val) {
+                recorder.axes.insert(axis, val);
+                frame.gamepad_axes.push((axis, val));
+            }
+        }
+    }
+
+    recorder.recording.frames.push(frame);
+}
+
+fn record_touch(
+    mut recorder: ResMut<Recorder>,
+    stick: Option<Res<VirtualStick>>,
+    mut tap_evr: EventReader<TouchTapEvt>,
+) {
+    if let Some(frame) = recorder.recording.frames.last_mut() {
+        if let Some(stick) = stick {
+            frame.virtual_stick = stick.get_value();
+        }
+
+        frame.touch_taps = tap_evr.iter().map(|ev| ev.screen_pos).collect();
+    }
+}
+
+fn save_recording(
+    mut recorder: ResMut<Recorder>,
+    mut exit_evr: EventReader<AppExit>,
+    time: Res<Time>,
+) {
+    let is_exiting = exit_evr.iter().next().is_some();
+    recorder.flush_timer.tick(time.delta());
+
+    if !is_exiting && !recorder.flush_timer.just_finished() {
+        return;
+    }
+
+    match ron::ser::to_string_pretty(&recorder.recording, ron::ser::PrettyConfig::default()) {
+        Ok(data) => match storage::save(RECORDING_KEY, &data) {
+            Ok(_) if is_exiting => info!(
+                "saved a recording of {} frames",
+                recorder.recording.frames.len()
+            ),
+            Ok(_) => {}
+            Err(err) => warn!("Failed to save recording: {err}"),
+        },
+        Err(err) => warn!("Failed to serialize recording: {err}"),
+    }
+}
+
+fn replay_events(
+    replayer: Res<Replayer>,
+    mut gamepad_evw: EventWriter<GamepadEvent>,
+    mut focus_events: ResMut<Events<WindowFocused>>,
+) {
+    // real focus changes would pause the game at different frames
+    focus_events.clear();
+
+    // connections are sent before the input systems run, so the gamepads are known by the time the frame replays
+    if let Some(frame) = replayer.recording.frames.get(replayer.frame) {
+        for gamepad in frame.gamepads_connected.iter() {
+            gamepad_evw.send(GamepadEvent(*gamepad, GamepadEventType::Connected));
+        }
+
+        for gamepad in frame.gamepads_disconnected.iter() {
+            gamepad_evw.send(GamepadEvent(*gamepad, GamepadEventType::Disconnected));
+        }
+
+        for focused in frame.window_focus.iter() {
+            focus_events.send(WindowFocused {
+                id: WindowId::primary(),
+                focused: *focused,
+            });
+        }
+    }
+}
+
+fn replay_frame(
+    mut replayer: ResMut<Replayer>,
+    mut keys: ResMut<Input<KeyCode>>,
+    mut gamepad_btns: ResMut<Input<GamepadButton>>,
+    mut mouse_btns: ResMut<Input<MouseButton>>,
+    mut axes: ResMut<Axis<GamepadAxis>>,
+    mut wnds: ResMut<Windows>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let frame = match replayer.recording.frames.get(replayer.frame) {
+        Some(frame) => frame.clone(),
+        None => {
+            info!("replay finished");
+            exit.send(AppExit);
+            return;
+        }
+    };
+
+    replayer.frame += 1;
+    replayer.keys.clear();
+    replayer.gamepad_btns.clear();
+    replayer.mouse_btns.clear();
+
+    for key in frame.keys_pressed {
+        replayer.keys.press(key);
+    }
+
+    for key in frame.keys_released {
+        replayer.keys.release(key);
+    }
+
+    for btn in frame.gamepad_btns_pressed {
+        replayer.gamepad_btns.press(btn);
+    }
+
+    for btn in frame.gamepad_btns_released {
+        replayer.gamepad_btns.release(btn);
+    }
+
+    for btn in frame.mouse_btns_pressed {
+        replayer.mouse_btns.press(btn);
+    }
+
+    for btn in frame.mouse_btns_released {
+        replayer.mouse_btns.release(btn);
+    }
+
+    for (axis, val) in frame.gamepad_axes {
+        axes.set(axis, val);
+    }
+
+    // real input is overwritten, so it doesn't interfere with the replay
+    *keys = replayer.keys.clone();
+    *gamepad_btns = replayer.gamepad_btns.clone();
+    *mouse_btns = replayer.mouse_btns.clone();
+
+    if let Some(wnd) = wnds.get_primary_mut() {
+        wnd.update_cursor_position_from_backend(frame.cursor_pos);
+    }
+}
+
+fn replay_touch(
+    replayer: Res<Replayer>,
+    mut axes: ResMut<Axis<GamepadAxis>>,
+    mut tap_events: ResMut<Events<TouchTapEvt>>,
+) {
+    let frame = replayer.get_current_frame();
+    let value = frame.map_or(Vec2::ZERO, |f| f.virtual_stick);
+
+    tap_events.clear();
+
+    for screen_pos in frame.iter().flat_map(|f| f.touch_taps.iter()) {
+        tap_events.send(TouchTapEvt {
+            screen_pos: *screen_pos,
+        });
+    }
+
+    axes.set(
+        GamepadAxis(VIRTUAL_STICK_GAMEPAD, GamepadAxisType::LeftStickX),
+        value.x,
+    );
+    axes.set(
+        GamepadAxis(VIRTUAL_STICK_GAMEPAD, GamepadAxisType::LeftStickY),
+        value.y,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::RngPlugin;
+    use bevy::{
+        input::{keyboard::KeyboardInput, ElementState, InputPlugin},
+        window::WindowPlugin,
+    };
+    use bevy_time::TimePlugin;
+    use rand::Rng;
+
+    fn get_app(mode: ReplayMode) -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(InputPlugin)
+            .add_plugin(WindowPlugin::default())
+            .add_plugin(TimePlugin)
+            .add_event::<TouchTapEvt>()
+            .add_plugin(RngPlugin)
+            .insert_resource(mode)
+            .add_plugin(ReplayPlugin);
+        app
+    }
+
+    fn send_key(app: &mut App, key: KeyCode, state: ElementState) {
+        app.world
+            .get_resource_mut::<Events<KeyboardInput>>()
+            .unwrap()
+            .send(KeyboardInput {
+                scan_code: 0,
+                key_code: Some(key),
+                state,
+            });
+    }
+
+    fn get_rolls(app: &mut App) -> Vec<u32> {
+        let mut rng = app.world.get_resource_mut::<GameRng>().unwrap();
+        (0..5).map(|_| rng.gen_range(0..1000)).collect()
+    }
+
+    #[test]
+    fn recording_roundtrips_through_ron() {
+        let recording = Recording {
+            version: RECORDING_VERSION,
+            seed: 42,
+            frames: vec![RecordedFrame {
+                delta_sec: 0.016,
+                keys_pressed: vec![KeyCode::Space],
+                gamepad_btns_pressed: vec![GamepadButton(Gamepad(0), GamepadButtonType::South)],
+                gamepad_axes: vec![(GamepadAxis(Gamepad(0), GamepadAxisType::LeftStickX), 0.5)],
+                mouse_btns_pressed: vec![MouseButton::Left],
+                cursor_pos: Some(Vec2::new(10., 20.)),
+                window_focus: vec![false],
+                touch_taps: vec![Vec2::new(30., 40.)],
+                ..Default::default()
+            }],
+        };
+
+        let data = ron::to_string(&recording).unwrap();
+        assert_eq!(ron::from_str::<Recording>(&data).unwrap(), recording);
+    }
+
+    #[test]
+    fn replay_reproduces_recorded_session() {
+        let mut record_app = get_app(ReplayMode::Record);
+        let mut recorded_presses = Vec::new();
+
+        for (i, key) in [KeyCode::Space, KeyCode::R, KeyCode::A].iter().enumerate() {
+            send_key(&mut record_app, *key, ElementState::Pressed);
+            record_app.update();
+            recorded_presses.push(
+                record_app
+                    .world
+                    .get_resource::<Input<KeyCode>>()
+                    .unwrap()
+                    .get_just_pressed()
+                    .copied()
+                    .collect::<Vec<_>>(),
+            );
+
+            if i % 2 == 0 {
+                send_key(&mut record_app, *key, ElementState::Released);
+            }
+        }
+
+        let recorded_rolls = get_rolls(&mut record_app);
+        let recording = record_app
+            .world
+            .get_resource::<Recorder>()
+            .unwrap()
+            .get_recording()
+            .clone();
+
+        let mut replay_app = get_app(ReplayMode::Replay(recording.clone()));
+        let mut replayed_presses = Vec::new();
+
+        for frame in recording.frames.iter() {
+            replay_app.update();
+
+            let replayer = replay_app.world.get_resource::<Replayer>().unwrap();
+            assert_eq!(replayer.get_frame_delta(), Some(frame.delta_sec));
+            // the user facing time scale is left alone
+            assert_eq!(replay_app.world.get_resource::<TimeScale>().unwrap().0, 1.);
+
+            replayed_presses.push(
+                replay_app
+                    .world
+                    .get_resource::<Input<KeyCode>>()
+                    .unwrap()
+                    .get_just_pressed()
+                    .copied()
+                    .collect::<Vec<_>>(),
+            );
+        }
+
+        assert_eq!(replayed_presses, recorded_presses);
+        assert_eq!(get_rolls(&mut replay_app), recorded_rolls);
+        assert!(replay_app
+            .world
+            .get_resource::<Replayer>()
+            .unwrap()
+            .is_finished());
+    }
+}
@@ -1,21 +1,34 @@
 use crate::{
-    animation::{get_scale_out_anim, TweenDoneAction},
-    input_binding::{InputAction, PlayerInput},
+    game_state::GameStateStack,
+    input_binding::{PlayerAction, PlayerInput},
+    replay::{get_scaled_delta, Replayer},
+    schedule::{PhaseSystem, UpdatePhase},
+    tween::{get_scale_out_anim, TweenDoneAction},
     GameState,
 };
 use bevy::prelude::*;
-use bevy_time::{ScaledTime, ScaledTimeDelta};
+use bevy_time::*;
 
 pub struct ResetPlugin;
 impl Plugin for ResetPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         app.init_resource::<ResetData>()
-            .add_system_set(SystemSet::on_enter(GameState::Reset).with_system(start_reset))
-            .add_system_set(SystemSet::on_update(GameState::Reset).with_system(reset))
-            .add_system_set(SystemSet::on_update(GameState::Game).with_system(handle_reset_input));
+            .add_system_set(
+                SystemSet::on_enter(GameState::Reset)
+                    .with_system(start_reset.in_phase(UpdatePhase::Logic)),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Reset)
+                    .with_system(reset.in_phase(UpdatePhase::Logic)),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Game)
+                    .with_system(handle_reset_input.in_phase(UpdatePhase::Logic)),
+            );
     }
 }
 
+/// Root entities which survive a reset, e.g. cameras and the ui root
 #[derive(Component)]
 pub struct Persistent;
 
@@ -24,13 +37,12 @@ struct ResetData {
     reset_in: Option<Timer>,
 }
 
-fn handle_reset_input(mut input: ResMut<PlayerInput>, mut state: ResMut<State<GameState>>) {
-    for id in 1..=4 {
-        if input.just_pressed(id, InputAction::Reset) {
-            input.use_button_action(id, InputAction::Reset);
-            state.overwrite_push(GameState::Reset).unwrap();
-            break;
-        }
+fn handle_reset_input(input_q: Query<&PlayerInput>, mut state: ResMut<State<GameState>>) {
+    if input_q
+        .iter()
+        .any(|input| input.just_pressed(PlayerAction::Reset))
+    {
+        state.go_to(GameState::Reset).unwrap();
     }
 }
 
@@ -54,13 +66,18 @@ fn start_reset(
     reset.reset_in = Some(Timer::from_seconds(0.6, false));
 }
 
-fn reset(mut state: ResMut<State<GameState>>, mut reset: ResMut<ResetData>, time: ScaledTime) {
+fn reset(
+    mut state: ResMut<State<GameState>>,
+    mut reset: ResMut<ResetData>,
+    time: ScaledTime,
+    replayer: Option<Res<Replayer>>,
+) {
     if let Some(timer) = reset.reset_in.as_mut() {
-        timer.tick(time.scaled_delta());
+        timer.tick(get_scaled_delta(&time, replayer.as_deref()));
 
         if timer.just_finished() {
             reset.reset_in = None;
-            state.overwrite_push(GameState::Game).unwrap();
+            state.go_to(GameState::Game).unwrap();
         }
     }
 }
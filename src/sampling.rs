@@ -0,0 +1,229 @@
+// image sampler settings per asset/folder, configured in `assets/images.sampling.ron`
+
+use crate::schedule::{PhaseSystem, UpdatePhase};
+use bevy::{
+    asset::{AssetLoader, AssetPath, BoxedFuture, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    render::render_resource::{AddressMode, FilterMode, SamplerDescriptor},
+};
+use serde::Deserialize;
+
+pub struct SamplingPlugin;
+impl Plugin for SamplingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ImageSampling>()
+            .add_asset::<SamplingManifest>()
+            .init_asset_loader::<SamplingManifestLoader>()
+            .add_startup_system(load_manifest)
+            .add_system(apply_manifest.in_phase(UpdatePhase::Render))
+            .add_system(
+                set_img_sampler
+                    .in_phase(UpdatePhase::Render)
+                    .after(apply_manifest),
+            );
+    }
+}
+
+pub const SAMPLING_MANIFEST_PATH: &str = "images.sampling.ron";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum SamplerFilter {
+    Nearest,
+    Linear,
+    /// Linear filtering between mip levels, only has an effect on images which have mips
+    Mipmapped,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum SamplerAddressMode {
+    ClampToEdge,
+    Repeat,
+    MirrorRepeat,
+}
+
+impl From<SamplerAddressMode> for AddressMode {
+    fn from(mode: SamplerAddressMode) -> Self {
+        match mode {
+            SamplerAddressMode::ClampToEdge => AddressMode::ClampToEdge,
+            SamplerAddressMode::Repeat => AddressMode::Repeat,
+            SamplerAddressMode::MirrorRepeat => AddressMode::MirrorRepeat,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct SamplerSettings {
+    pub filter: SamplerFilter,
+    pub address_mode: SamplerAddressMode,
+}
+
+impl Default for SamplerSettings {
+    fn default() -> Self {
+        Self {
+            // some AA (quite fuzzy though)
+            filter: SamplerFilter::Linear,
+            address_mode: SamplerAddressMode::ClampToEdge,
+        }
+    }
+}
+
+impl SamplerSettings {
+    pub fn apply(&self, descriptor: &mut SamplerDescriptor<'static>) {
+        let (filter, mipmap_filter) = match self.filter {
+            SamplerFilter::Nearest => (FilterMode::Nearest, FilterMode::Nearest),
+            SamplerFilter::Linear => (FilterMode::Linear, FilterMode::Nearest),
+            SamplerFilter::Mipmapped => (FilterMode::Linear, FilterMode::Linear),
+        };
+
+        descriptor.mag_filter = filter;
+        descriptor.min_filter = filter;
+        descriptor.mipmap_filter = mipmap_filter;
+        descriptor.address_mode_u = self.address_mode.into();
+        descriptor.address_mode_v = self.address_mode.into();
+        descriptor.address_mode_w = self.address_mode.into();
+    }
+
+    fn is_applied(&self, descriptor: &SamplerDescriptor<'static>) -> bool {
+        let mut expected = descriptor.clone();
+        self.apply(&mut expected);
+
+        expected.mag_filter == descriptor.mag_filter
+            && expected.min_filter == descriptor.min_filter
+            && expected.mipmap_filter == descriptor.mipmap_filter
+            && expected.address_mode_u == descriptor.address_mode_u
+            && expected.address_mode_v == descriptor.address_mode_v
+            && expected.address_mode_w == descriptor.address_mode_w
+    }
+}
+
+/// A rule applies to the image with the given path or to every image inside the given folder
+#[derive(Debug, Clone, Deserialize)]
+pub struct SamplingRule {
+    pub path: String,
+    #[serde(default)]
+    pub settings: SamplerSettings,
+}
+
+#[derive(Debug, Clone, Deserialize, TypeUuid)]
+#[uuid = "9025fde2-f758-43e9-9e62-bca73c22f22a"]
+pub struct SamplingManifest {
+    #[serde(default)]
+    pub default: Option<SamplerSettings>,
+    #[serde(default)]
+    pub rules: Vec<SamplingRule>,
+}
+
+/// The default can be changed in code, the manifest's default takes precedence once it's loaded
+#[derive(Default)]
+pub struct ImageSampling {
+    pub default: SamplerSettings,
+    pub rules: Vec<SamplingRule>,
+}
+
+impl ImageSampling {
+    /// The most specific (longest) matching rule wins
+    pub fn get_settings(&self, path: &AssetPath) -> SamplerSettings {
+        let path = path.path().to_string_lossy().replace('\\', "/");
+
+        self.rules
+            .iter()
+            .filter(|rule| {
+                path == rule.path
+                    || path.starts_with(&format!("{}/", rule.path.trim_end_matches('/')))
+            })
+            .max_by_key(|rule| rule.path.len())
+            .map_or(self.default, |rule| rule.settings)
+    }
+}
+
+#[derive(Default)]
+struct SamplingManifestLoader;
+
+impl AssetLoader for SamplingManifestLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let manifest: SamplingManifest = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(manifest));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["sampling.ron"]
+    }
+}
+
+/// Keeps the manifest loaded, so changes get hot-reloaded
+struct SamplingManifestHandle(Handle<SamplingManifest>);
+
+fn load_manifest(mut commands: Commands, ass: Res<AssetServer>) {
+    commands.insert_resource(SamplingManifestHandle(ass.load(SAMPLING_MANIFEST_PATH)));
+}
+
+fn apply_manifest(
+    mut ev_manifest: EventReader<AssetEvent<SamplingManifest>>,
+    manifests: Res<Assets<SamplingManifest>>,
+    mut sampling: ResMut<ImageSampling>,
+) {
+    for ev in ev_manifest.iter() {
+        match ev {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+                if let Some(manifest) = manifests.get(handle) {
+                    if let Some(default) = manifest.default {
+                        sampling.default = default;
+                    }
+
+                    sampling.rules = manifest.rules.clone();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn set_img_sampler(
+    mut ev_asset: EventReader<AssetEvent<Image>>,
+    mut assets: ResMut<Assets<Image>>,
+    sampling: Res<ImageSampling>,
+    ass: Res<AssetServer>,
+) {
+    let handles: Vec<Handle<Image>> = if sampling.is_changed() {
+        // reapply to everything, images might've loaded before the manifest
+        assets.iter().map(|(id, _)| assets.get_handle(id)).collect()
+    } else {
+        ev_asset
+            .iter()
+            .filter_map(|ev| match ev {
+                AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+                    Some(handle.clone())
+                }
+                _ => None,
+            })
+            .collect()
+    };
+
+    for handle in handles {
+        let settings = match ass.get_handle_path(&handle) {
+            Some(path) => sampling.get_settings(&path),
+            // images created in code
+            None => sampling.default,
+        };
+
+        // only touch the image when needed, as get_mut triggers another modified event
+        let is_applied = assets
+            .get(&handle)
+            .map_or(true, |img| settings.is_applied(&img.sampler_descriptor));
+
+        if !is_applied {
+            if let Some(img) = assets.get_mut(&handle) {
+                settings.apply(&mut img.sampler_descriptor);
+            }
+        }
+    }
+}
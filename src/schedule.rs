@@ -0,0 +1,136 @@
+use bevy::{
+    ecs::schedule::{
+        graph_utils::{build_dependency_graph, topological_order},
+        ParallelSystemDescriptor, ReportExecutionOrderAmbiguities, SystemContainer,
+    },
+    prelude::*,
+};
+
+pub struct SchedulePlugin;
+impl Plugin for SchedulePlugin {
+    fn build(&self, app: &mut App) {
+        // every phase has at least one system, so the chain holds even when a phase is empty
+        for phase in UpdatePhase::ALL {
+            app.add_system(phase_anchor.in_phase(phase));
+        }
+
+        if cfg!(feature = "dev") {
+            app.insert_resource(ReportExecutionOrderAmbiguities);
+        }
+    }
+}
+
+/// Update stage phases, each one runs after the previous one has finished.
+/// Add systems with [`PhaseSystem::in_phase`] instead of labelling them directly.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, SystemLabel)]
+pub enum UpdatePhase {
+    /// Reading devices into the input resources and action maps
+    Input,
+    /// Ui and gameplay reacting to input, state changes
+    Logic,
+    Physics,
+    Movement,
+    /// Tweens, palette colors, ui visuals
+    Animation,
+    Audio,
+    /// Camera, scaling and image settings
+    Render,
+}
+
+impl UpdatePhase {
+    pub const ALL: [UpdatePhase; 7] = [
+        UpdatePhase::Input,
+        UpdatePhase::Logic,
+        UpdatePhase::Physics,
+        UpdatePhase::Movement,
+        UpdatePhase::Animation,
+        UpdatePhase::Audio,
+        UpdatePhase::Render,
+    ];
+
+    fn get_index(&self) -> usize {
+        Self::ALL.iter().position(|p| p == self).unwrap()
+    }
+
+    pub fn get_prev(&self) -> Option<UpdatePhase> {
+        self.get_index().checked_sub(1).map(|i| Self::ALL[i])
+    }
+
+    pub fn get_next(&self) -> Option<UpdatePhase> {
+        Self::ALL.get(self.get_index() + 1).copied()
+    }
+}
+
+pub trait PhaseSystem<Params> {
+    /// Labels the system with the phase and orders it between the neighbouring phases
+    fn in_phase(self, phase: UpdatePhase) -> ParallelSystemDescriptor;
+}
+
+impl<S, Params> PhaseSystem<Params> for S
+where
+    S: ParallelSystemDescriptorCoercion<Params>,
+{
+    fn in_phase(self, phase: UpdatePhase) -> ParallelSystemDescriptor {
+        let mut descriptor = self.label(phase);
+
+        if let Some(prev) = phase.get_prev() {
+            descriptor = descriptor.after(prev);
+        }
+
+        if let Some(next) = phase.get_next() {
+            descriptor = descriptor.before(next);
+        }
+
+        descriptor
+    }
+}
+
+fn phase_anchor() {}
+
+/// Logs the update stage systems in a valid execution order along with their phase.
+/// Ambiguities between systems of the same phase are reported by bevy in dev builds.
+pub fn dump_update_order(app: &App) {
+    let stage = match app.schedule.get_stage::<SystemStage>(&CoreStage::Update) {
+        Some(stage) => stage,
+        None => return,
+    };
+    let systems = stage.parallel_systems();
+    let order = match topological_order(&build_dependency_graph(systems)) {
+        Ok(order) => order,
+        Err(_) => {
+            warn!("Update stage systems have a dependency cycle");
+            return;
+        }
+    };
+
+    let mut dump = "Update order:".to_string();
+    let mut unassigned = 0;
+
+    for i in order {
+        let system = &systems[i];
+        let phase = get_phase(system);
+
+        if phase.is_none() {
+            unassigned += 1;
+        }
+
+        dump.push_str(&format!(
+            "\n  {:<10} {}",
+            phase.map_or("-".to_string(), |p| format!("{p:?}")),
+            system.name()
+        ));
+    }
+
+    info!("{dump}");
+
+    if unassigned > 0 {
+        warn!("{unassigned} update systems aren't assigned to a phase");
+    }
+}
+
+fn get_phase(system: &impl SystemContainer) -> Option<UpdatePhase> {
+    UpdatePhase::ALL.into_iter().find(|phase| {
+        let phase: &dyn SystemLabel = phase;
+        system.labels().iter().any(|label| label.as_ref() == phase)
+    })
+}
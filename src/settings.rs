@@ -0,0 +1,354 @@
+use crate::{
+    input_binding::InputBindings,
+    palette::{Palette, PaletteTheme},
+    schedule::{PhaseSystem, UpdatePhase},
+    sfx::AudioBus,
+    storage,
+    widget::{SelectorChangedEvt, SliderChangedEvt, ToggleChangedEvt, WidgetTarget},
+};
+use bevy::{app::AppExit, prelude::*, window::WindowMode};
+use bevy_time::*;
+use serde::{Deserialize, Serialize};
+
+pub struct SettingsPlugin;
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(load_settings())
+            .init_resource::<SettingsSaveDebounce>()
+            .add_system(handle_setting_widgets.in_phase(UpdatePhase::Logic))
+            .add_system(queue_settings_save.in_phase(UpdatePhase::Logic))
+            .add_system(apply_palette_theme.in_phase(UpdatePhase::Animation))
+            .add_system(apply_window_settings.in_phase(UpdatePhase::Render))
+            .add_system_to_stage(CoreStage::Last, save_settings);
+    }
+}
+
+const SETTINGS_KEY: &str = "settings";
+/// Bumped whenever the [`Settings`] layout changes, e.g. 2 added the input bindings.
+/// Every older version needs an arm in [`parse_settings`], which can parse it as the current struct
+/// if `#[serde(default)]` covers the change.
+pub const SETTINGS_VERSION: u32 = 2;
+const SAVE_DEBOUNCE_SEC: f32 = 1.;
+pub const VOLUME_STEP: f32 = 0.1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub master_volume: f32,
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+    pub fullscreen: bool,
+    pub vsync: bool,
+    pub palette_theme: PaletteTheme,
+    pub reduce_motion: bool,
+    pub bindings: InputBindings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            master_volume: 1.,
+            music_volume: 0.8,
+            sfx_volume: 0.8,
+            fullscreen: false,
+            vsync: true,
+            palette_theme: PaletteTheme::default(),
+            reduce_motion: false,
+            bindings: InputBindings::default(),
+        }
+    }
+}
+
+/// Settings before input bindings were added
+#[derive(Deserialize)]
+struct SettingsV1 {
+    master_volume: f32,
+    music_volume: f32,
+    sfx_volume: f32,
+    fullscreen: bool,
+    vsync: bool,
+    palette_theme: PaletteTheme,
+    reduce_motion: bool,
+}
+
+impl From<SettingsV1> for Settings {
+    fn from(v1: SettingsV1) -> Self {
+        Self {
+            master_volume: v1.master_volume,
+            music_volume: v1.music_volume,
+            sfx_volume: v1.sfx_volume,
+            fullscreen: v1.fullscreen,
+            vsync: v1.vsync,
+            palette_theme: v1.palette_theme,
+            reduce_motion: v1.reduce_motion,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SettingsHeader {
+    #[serde(default)]
+    version: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SettingsFile<T> {
+    version: u32,
+    settings: T,
+}
+
+#[derive(Debug)]
+enum SettingsLoadError {
+    Parse(ron::Error),
+    UnsupportedVersion(u32),
+}
+
+impl From<ron::Error> for SettingsLoadError {
+    fn from(err: ron::Error) -> Self {
+        SettingsLoadError::Parse(err)
+    }
+}
+
+#[derive(Default)]
+struct SettingsSaveDebounce {
+    timer: Option<Timer>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingKind {
+    Volume(AudioBus),
+    Fullscreen,
+    Vsync,
+    PaletteTheme,
+    ReduceMotion,
+}
+
+impl Settings {
+    pub fn get_volume(&self, bus: AudioBus) -> f32 {
+        match bus {
+            AudioBus::Master => self.master_volume,
+            AudioBus::Music => self.music_volume,
+            AudioBus::Sfx => self.sfx_volume,
+        }
+    }
+
+    /// Bus volume scaled by the master volume
+    pub fn get_effective_volume(&self, bus: AudioBus) -> f32 {
+        match bus {
+            AudioBus::Master => self.master_volume,
+            _ => self.master_volume * self.get_volume(bus),
+        }
+    }
+
+    pub fn set_volume(&mut self, bus: AudioBus, volume: f32) {
+        let volume = volume.clamp(0., 1.);
+        match bus {
+            AudioBus::Master => self.master_volume = volume,
+            AudioBus::Music => self.music_volume = volume,
+            AudioBus::Sfx => self.sfx_volume = volume,
+        }
+    }
+
+    pub fn set_toggle(&mut self, kind: SettingKind, on: bool) {
+        match kind {
+            SettingKind::Fullscreen => self.fullscreen = on,
+            SettingKind::Vsync => self.vsync = on,
+            SettingKind::ReduceMotion => self.reduce_motion = on,
+            _ => warn!("Setting {kind:?} is not a toggle"),
+        }
+    }
+}
+
+impl SettingKind {
+    pub fn get_name(&self) -> String {
+        match self {
+            SettingKind::Volume(bus) => format!("{} volume", bus.get_name()),
+            SettingKind::Fullscreen => "Fullscreen".to_string(),
+            SettingKind::Vsync => "Vsync".to_string(),
+            SettingKind::PaletteTheme => "Theme".to_string(),
+            SettingKind::ReduceMotion => "Reduce motion".to_string(),
+        }
+    }
+}
+
+fn parse_settings(data: &str) -> Result<Settings, SettingsLoadError> {
+    let header: SettingsHeader = ron::from_str(data)?;
+
+    match header.version {
+        SETTINGS_VERSION => Ok(ron::from_str::<SettingsFile<Settings>>(data)?.settings),
+        // older versions get parsed as their legacy struct and converted
+        1 => Ok(ron::from_str::<SettingsFile<SettingsV1>>(data)?
+            .settings
+            .into()),
+        version => Err(SettingsLoadError::UnsupportedVersion(version)),
+    }
+}
+
+fn parse_settings_or_default(data: &str) -> Settings {
+    match parse_settings(data) {
+        Ok(settings) => settings,
+        Err(err) => {
+            warn!("Failed to load settings, falling back to defaults: {err:?}");
+            Settings::default()
+        }
+    }
+}
+
+fn load_settings() -> Settings {
+    storage::load(SETTINGS_KEY)
+        .map(|data| parse_settings_or_default(&data))
+        .unwrap_or_default()
+}
+
+fn serialize_settings(settings: &Settings) -> Result<String, ron::Error> {
+    let file = SettingsFile {
+        version: SETTINGS_VERSION,
+        settings,
+    };
+
+    ron::ser::to_string_pretty(&file, ron::ser::PrettyConfig::default())
+}
+
+fn write_settings(settings: &Settings) {
+    match serialize_settings(settings) {
+        Ok(data) => {
+            if let Err(err) = storage::save(SETTINGS_KEY, &data) {
+                warn!("Failed to save settings: {err}");
+            }
+        }
+        Err(err) => warn!("Failed to serialize settings: {err}"),
+    }
+}
+
+fn queue_settings_save(settings: Res<Settings>, mut debounce: ResMut<SettingsSaveDebounce>) {
+    if settings.is_changed() && !settings.is_added() {
+        // restart the timer on every change so e.g. a dragged volume slider gets saved only once
+        debounce.timer = Some(Timer::from_seconds(SAVE_DEBOUNCE_SEC, false));
+    }
+}
+
+fn save_settings(
+    settings: Res<Settings>,
+    mut debounce: ResMut<SettingsSaveDebounce>,
+    mut exit_evr: EventReader<AppExit>,
+    time: ScaledTime,
+) {
+    if let Some(timer) = debounce.timer.as_mut() {
+        // unscaled time so the save still happens while paused
+        timer.tick(time.delta());
+
+        if timer.finished() || exit_evr.iter().next().is_some() {
+            debounce.timer = None;
+            write_settings(&settings);
+        }
+    }
+}
+
+fn handle_setting_widgets(
+    mut settings: ResMut<Settings>,
+    mut slider_evr: EventReader<SliderChangedEvt>,
+    mut toggle_evr: EventReader<ToggleChangedEvt>,
+    mut selector_evr: EventReader<SelectorChangedEvt>,
+) {
+    for ev in slider_evr.iter() {
+        if let WidgetTarget::Setting(SettingKind::Volume(bus)) = ev.target {
+            settings.set_volume(bus, ev.value);
+        }
+    }
+
+    for ev in toggle_evr.iter() {
+        let WidgetTarget::Setting(kind) = ev.target;
+        settings.set_toggle(kind, ev.on);
+    }
+
+    for ev in selector_evr.iter() {
+        if let WidgetTarget::Setting(SettingKind::PaletteTheme) = ev.target {
+            if let Some(theme) = PaletteTheme::ALL.get(ev.index) {
+                settings.palette_theme = *theme;
+            }
+        }
+    }
+}
+
+fn apply_window_settings(settings: Res<Settings>, mut windows: ResMut<Windows>) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    if let Some(window) = windows.get_primary_mut() {
+        let mode = if settings.fullscreen {
+            WindowMode::BorderlessFullscreen
+        } else {
+            WindowMode::Windowed
+        };
+
+        if window.mode() != mode {
+            window.set_mode(mode);
+        }
+
+        if window.vsync() != settings.vsync {
+            window.set_vsync(settings.vsync);
+        }
+    }
+}
+
+fn apply_palette_theme(settings: Res<Settings>, mut palette: ResMut<Palette>) {
+    if settings.is_changed() && palette.get_theme() != settings.palette_theme {
+        *palette = Palette::from_theme(settings.palette_theme);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn current_version_roundtrip() {
+        let settings = Settings {
+            master_volume: 0.3,
+            fullscreen: true,
+            palette_theme: PaletteTheme::Light,
+            ..Default::default()
+        };
+        let data = serialize_settings(&settings).unwrap();
+
+        assert_eq!(parse_settings(&data).unwrap(), settings);
+    }
+
+    #[test]
+    fn old_version_is_migrated() {
+        let data = "(
+            version: 1,
+            settings: (
+                master_volume: 0.5,
+                music_volume: 0.4,
+                sfx_volume: 0.3,
+                fullscreen: true,
+                vsync: false,
+                palette_theme: HighContrast,
+                reduce_motion: true,
+            ),
+        )";
+        let settings = parse_settings(data).unwrap();
+
+        assert_eq!(settings.master_volume, 0.5);
+        assert_eq!(settings.palette_theme, PaletteTheme::HighContrast);
+        assert!(settings.reduce_motion);
+        // fields added since then get their defaults
+        assert_eq!(settings.bindings, InputBindings::default());
+    }
+
+    #[test]
+    fn invalid_data_falls_back_to_defaults() {
+        assert!(matches!(
+            parse_settings("(version: 1, settings: (master_vol"),
+            Err(SettingsLoadError::Parse(_))
+        ));
+        assert!(matches!(
+            parse_settings("(version: 999, settings: ())"),
+            Err(SettingsLoadError::UnsupportedVersion(999))
+        ));
+        assert_eq!(parse_settings_or_default("not ron"), Settings::default());
+    }
+}
@@ -2,14 +2,21 @@ use std::ops::RangeInclusive;
 
 use bevy::{ecs::system::Resource, prelude::*};
 use bevy_kira_audio::{Audio, AudioChannel};
-use rand::*;
+use rand::Rng;
 
-use crate::menu::ButtonActiveEvt;
+use crate::{
+    menu::ButtonActiveEvt,
+    rng::GameRng,
+    schedule::{PhaseSystem, UpdatePhase},
+    settings::Settings,
+};
 
 pub struct SfxPlugin;
 impl Plugin for SfxPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         app.add_event::<QueueSoundEvt>()
+            .add_system(apply_music_volume.in_phase(UpdatePhase::Audio))
+            // the queue runs after update, so every phase can queue sounds
             .add_system_set_to_stage(
                 CoreStage::Last,
                 SystemSet::new()
@@ -35,6 +42,25 @@ pub enum SfxPhase {
     ProcessQueue,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AudioBus {
+    Master,
+    Music,
+    Sfx,
+}
+
+impl AudioBus {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            AudioBus::Master => "Master",
+            AudioBus::Music => "Music",
+            AudioBus::Sfx => "Sfx",
+        }
+    }
+}
+
+pub const MUSIC_CHANNEL: &str = "music";
+
 pub trait SfxEvt {
     fn get_volume(&self) -> f32;
 }
@@ -49,9 +75,27 @@ pub struct QueueSoundEvt {
     volume: f32,
     file_prefix: String,
     range: RangeInclusive<u8>,
+    /// Sounds share a channel by their prefix, the volume is set per channel
     channel_suffix: Option<usize>,
 }
 
+impl QueueSoundEvt {
+    pub fn new(file_prefix: &str, range: RangeInclusive<u8>, volume: f32) -> Self {
+        Self {
+            volume,
+            file_prefix: file_prefix.to_string(),
+            range,
+            ..Default::default()
+        }
+    }
+
+    /// Plays on its own channel, so its volume doesn't affect other sounds with the same prefix
+    pub fn with_channel_suffix(mut self, suffix: usize) -> Self {
+        self.channel_suffix = Some(suffix);
+        self
+    }
+}
+
 impl Default for QueueSoundEvt {
     fn default() -> Self {
         Self {
@@ -67,9 +111,11 @@ fn play_queued_sounds(
     mut ev_r: EventReader<QueueSoundEvt>,
     asset_server: Res<AssetServer>,
     audio: Res<Audio>,
+    settings: Res<Settings>,
+    mut rng: ResMut<GameRng>,
 ) {
     for ev in ev_r.iter() {
-        let index = thread_rng().gen_range(ev.range.clone());
+        let index = rng.gen_range(ev.range.clone());
         let path = format!("audio/sfx/{}{index}.ogg", ev.file_prefix);
         let channel_key = if let Some(suffix) = ev.channel_suffix {
             format!("{}{}", ev.file_prefix, suffix)
@@ -77,9 +123,19 @@ fn play_queued_sounds(
             ev.file_prefix.clone()
         };
         let channel = AudioChannel::new(channel_key);
-        audio.set_volume_in_channel(ev.volume, &channel);
+        let volume = ev.volume * settings.get_effective_volume(AudioBus::Sfx);
+        audio.set_volume_in_channel(volume, &channel);
         audio.play_in_channel(asset_server.load(&path), &channel);
-        trace!("playing {path} at volume {}", ev.volume);
+        trace!("playing {path} at volume {volume}");
+    }
+}
+
+fn apply_music_volume(settings: Res<Settings>, audio: Res<Audio>) {
+    if settings.is_changed() {
+        audio.set_volume_in_channel(
+            settings.get_effective_volume(AudioBus::Music),
+            &AudioChannel::new(MUSIC_CHANNEL.to_owned()),
+        );
     }
 }
 
@@ -0,0 +1,64 @@
+// key/value persistence - files in the platform config dir on native, localStorage on web
+
+use crate::NAME;
+
+#[derive(Debug)]
+pub enum StorageError {
+    Unavailable,
+    Io(String),
+}
+
+impl std::fmt::Display for StorageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageError::Unavailable => write!(f, "storage is not available"),
+            StorageError::Io(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn get_path(key: &str) -> Option<std::path::PathBuf> {
+    // todo: use the real org name
+    directories::ProjectDirs::from("", "SecretPocketCat", NAME)
+        .map(|dirs| dirs.config_dir().join(format!("{key}.ron")))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load(key: &str) -> Option<String> {
+    std::fs::read_to_string(get_path(key)?).ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save(key: &str, data: &str) -> Result<(), StorageError> {
+    let path = get_path(key).ok_or(StorageError::Unavailable)?;
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| StorageError::Io(e.to_string()))?;
+    }
+
+    std::fs::write(path, data).map_err(|e| StorageError::Io(e.to_string()))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn get_local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+fn get_storage_key(key: &str) -> String {
+    format!("{NAME}.{key}")
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn load(key: &str) -> Option<String> {
+    get_local_storage()?.get_item(&get_storage_key(key)).ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn save(key: &str, data: &str) -> Result<(), StorageError> {
+    get_local_storage()
+        .ok_or(StorageError::Unavailable)?
+        .set_item(&get_storage_key(key), data)
+        .map_err(|e| StorageError::Io(format!("{e:?}")))
+}
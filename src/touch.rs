@@ -0,0 +1,356 @@
+use crate::{
+    game_state::GameState,
+    mouse::{get_world_pos, CursorCamera, CursorPosition, CursorWorldPosition, MouseSystem},
+    palette::{Palette, PaletteColor},
+    replay::Replayer,
+    schedule::{PhaseSystem, UpdatePhase},
+};
+use bevy::{prelude::*, utils::HashMap, window::WindowId};
+use bevy_time::*;
+
+pub struct TouchPlugin;
+impl Plugin for TouchPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TouchState>()
+            .init_resource::<VirtualStick>()
+            .add_event::<TouchTapEvt>()
+            .add_system(
+                track_touches
+                    .in_phase(UpdatePhase::Input)
+                    .label(TouchSystem::Track)
+                    .after(MouseSystem::StoreCursor),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::Game)
+                    .with_system(spawn_virtual_stick.in_phase(UpdatePhase::Logic)),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Game)
+                    .with_system(despawn_virtual_stick.in_phase(UpdatePhase::Logic)),
+            )
+            .add_system(update_virtual_stick_nodes.in_phase(UpdatePhase::Animation));
+    }
+}
+
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TouchSystem {
+    /// Writes the virtual stick axes
+    Track,
+}
+
+const TAP_MAX_SEC: f32 = 0.3;
+/// Max distance in pixels a touch can travel and still count as a tap
+const TAP_MAX_DISTANCE: f32 = 20.;
+
+/// The virtual stick writes to the left stick axes of this gamepad, which the keyboard player listens to
+pub const VIRTUAL_STICK_GAMEPAD: Gamepad = Gamepad(usize::MAX);
+
+/// A short touch which barely moved, the menus treat it as a confirm on the tapped button
+pub struct TouchTapEvt {
+    /// Window position with the origin in the bottom left corner (same as ui nodes)
+    pub screen_pos: Vec2,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TrackedTouch {
+    pub start_screen_pos: Vec2,
+    pub screen_pos: Vec2,
+    pub held_sec: f32,
+}
+
+/// All active touches, the first touch which isn't controlling the virtual stick drives the cursor
+#[derive(Default)]
+pub struct TouchState {
+    touches: HashMap<u64, TrackedTouch>,
+    primary_id: Option<u64>,
+    primary_world_pos: Option<Vec2>,
+    stick_id: Option<u64>,
+}
+
+impl TouchState {
+    pub fn iter(&self) -> impl Iterator<Item = (&u64, &TrackedTouch)> {
+        self.touches.iter()
+    }
+
+    pub fn get_primary(&self) -> Option<&TrackedTouch> {
+        self.primary_id.and_then(|id| self.touches.get(&id))
+    }
+}
+
+/// Optional on-screen stick for touch devices.
+/// Touching the left half of the screen during the game places the stick under the finger.
+pub struct VirtualStick {
+    pub enabled: bool,
+    /// In pixels
+    pub radius: f32,
+    pub deadzone: f32,
+    center: Option<Vec2>,
+    value: Vec2,
+}
+
+impl Default for VirtualStick {
+    fn default() -> Self {
+        Self {
+            // touch is only expected on the web build
+            enabled: cfg!(target_arch = "wasm32"),
+            radius: 60.,
+            deadzone: 0.15,
+            center: None,
+            value: Vec2::ZERO,
+        }
+    }
+}
+
+impl VirtualStick {
+    pub fn get_value(&self) -> Vec2 {
+        self.value
+    }
+}
+
+#[derive(Component)]
+struct VirtualStickBase;
+
+#[derive(Component)]
+struct VirtualStickKnob;
+
+/// Touches are top-left based except on mobile, where winit flips them already
+fn get_touch_screen_pos(touch_pos: Vec2, window_height: f32) -> Vec2 {
+    if cfg!(any(target_os = "android", target_os = "ios")) {
+        touch_pos
+    } else {
+        Vec2::new(touch_pos.x, window_height - touch_pos.y)
+    }
+}
+
+fn track_touches(
+    mut touch_state: ResMut<TouchState>,
+    mut stick: ResMut<VirtualStick>,
+    mut cursor_pos: ResMut<CursorWorldPosition>,
+    mut mouse_btns: ResMut<Input<MouseButton>>,
+    mut axes: ResMut<Axis<GamepadAxis>>,
+    mut tap_evw: EventWriter<TouchTapEvt>,
+    touches: Res<Touches>,
+    wnds: Res<Windows>,
+    state: Res<State<GameState>>,
+    camera_q: Query<(Entity, &Camera, &GlobalTransform), With<CursorCamera>>,
+    replayer: Option<Res<Replayer>>,
+    time: ScaledTime,
+) {
+    let state_ref = &mut *touch_state;
+    let wnd = match wnds.get_primary() {
+        Some(wnd) => wnd,
+        None => return,
+    };
+    let window_size = Vec2::new(wnd.width(), wnd.height());
+    let is_stick_active = stick.enabled && *state.current() == GameState::Game;
+
+    for touch in touches.iter_just_pressed() {
+        let screen_pos = get_touch_screen_pos(touch.position(), window_size.y);
+        state_ref.touches.insert(
+            touch.id(),
+            TrackedTouch {
+                start_screen_pos: screen_pos,
+                screen_pos,
+                held_sec: 0.,
+            },
+        );
+
+        if is_stick_active && state_ref.stick_id.is_none() && screen_pos.x < window_size.x * 0.5 {
+            state_ref.stick_id = Some(touch.id());
+            stick.center = Some(screen_pos);
+        } else if state_ref.primary_id.is_none() {
+            state_ref.primary_id = Some(touch.id());
+            mouse_btns.press(MouseButton::Left);
+        }
+    }
+
+    for (id, tracked) in state_ref.touches.iter_mut() {
+        if let Some(touch) = touches.get_pressed(*id) {
+            tracked.screen_pos = get_touch_screen_pos(touch.position(), window_size.y);
+            // real time, so menus work while paused
+            tracked.held_sec += time.delta().as_secs_f32();
+        }
+    }
+
+    for touch in touches
+        .iter_just_released()
+        .chain(touches.iter_just_cancelled())
+    {
+        let tracked = match state_ref.touches.remove(&touch.id()) {
+            Some(tracked) => tracked,
+            None => continue,
+        };
+
+        if state_ref.stick_id == Some(touch.id()) {
+            state_ref.stick_id = None;
+            stick.center = None;
+        } else {
+            if state_ref.primary_id == Some(touch.id()) {
+                state_ref.primary_id = None;
+                state_ref.primary_world_pos = None;
+                mouse_btns.release(MouseButton::Left);
+
+                if let Some(pos) = cursor_pos.0.as_mut() {
+                    pos.is_inside = false;
+                    pos.delta = Vec2::ZERO;
+                }
+            }
+
+            if tracked.held_sec <= TAP_MAX_SEC
+                && tracked.start_screen_pos.distance(tracked.screen_pos) <= TAP_MAX_DISTANCE
+            {
+                tap_evw.send(TouchTapEvt {
+                    screen_pos: tracked.screen_pos,
+                });
+            }
+        }
+    }
+
+    // stick
+    stick.value = match (
+        stick.center,
+        state_ref.stick_id.and_then(|id| state_ref.touches.get(&id)),
+    ) {
+        (Some(center), Some(tracked)) if is_stick_active => {
+            let value = ((tracked.screen_pos - center) / stick.radius).clamp_length_max(1.);
+            if value.length() < stick.deadzone {
+                Vec2::ZERO
+            } else {
+                value
+            }
+        }
+        _ => Vec2::ZERO,
+    };
+
+    // a replay feeds the recorded stick instead
+    if replayer.is_none() {
+        axes.set(
+            GamepadAxis(VIRTUAL_STICK_GAMEPAD, GamepadAxisType::LeftStickX),
+            stick.value.x,
+        );
+        axes.set(
+            GamepadAxis(VIRTUAL_STICK_GAMEPAD, GamepadAxisType::LeftStickY),
+            stick.value.y,
+        );
+    }
+
+    // the primary touch acts as the cursor
+    if let Some(tracked) = state_ref.get_primary().copied() {
+        let camera = camera_q
+            .iter()
+            .find(|(_, camera, _)| camera.window == WindowId::primary());
+
+        if let Some((camera_e, camera, camera_t)) = camera {
+            let world = get_world_pos(tracked.screen_pos, window_size, camera, camera_t);
+            let delta = state_ref
+                .primary_world_pos
+                .map_or(Vec2::ZERO, |prev| world - prev);
+            state_ref.primary_world_pos = Some(world);
+
+            cursor_pos.0 = Some(CursorPosition {
+                world,
+                screen: tracked.screen_pos,
+                delta,
+                is_inside: true,
+                window: WindowId::primary(),
+                camera: camera_e,
+            });
+        }
+    }
+}
+
+fn spawn_virtual_stick(mut commands: Commands, stick: Res<VirtualStick>, palette: Res<Palette>) {
+    if !stick.enabled {
+        return;
+    }
+
+    let mut base_color = palette.get_color(&PaletteColor::Button);
+    base_color.set_a(0.4);
+    let size = stick.radius * 2.;
+    let knob_size = stick.radius * 0.8;
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Px(size), Val::Px(size)),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            color: base_color.into(),
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(VirtualStickBase)
+        .with_children(|b| {
+            b.spawn_bundle(NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Px(knob_size), Val::Px(knob_size)),
+                    position_type: PositionType::Absolute,
+                    ..Default::default()
+                },
+                color: palette.get_color(&PaletteColor::ButtonFocus).into(),
+                visibility: Visibility { is_visible: false },
+                ..Default::default()
+            })
+            .insert(VirtualStickKnob);
+        });
+}
+
+fn despawn_virtual_stick(mut commands: Commands, stick_q: Query<Entity, With<VirtualStickBase>>) {
+    for e in stick_q.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
+
+fn update_virtual_stick_nodes(
+    stick: Res<VirtualStick>,
+    mut base_q: Query<(&mut Style, &mut Visibility), With<VirtualStickBase>>,
+    mut knob_q: Query<
+        (&mut Style, &mut Visibility),
+        (With<VirtualStickKnob>, Without<VirtualStickBase>),
+    >,
+) {
+    let knob_offset = stick.value * stick.radius;
+
+    for (mut style, mut visibility) in base_q.iter_mut() {
+        visibility.is_visible = stick.center.is_some();
+
+        if let Some(center) = stick.center {
+            style.position = Rect {
+                left: Val::Px(center.x - stick.radius),
+                bottom: Val::Px(center.y - stick.radius),
+                ..Default::default()
+            };
+        }
+    }
+
+    for (mut style, mut visibility) in knob_q.iter_mut() {
+        visibility.is_visible = stick.center.is_some();
+
+        // relative to the base
+        let knob_half_size = stick.radius * 0.4;
+        style.position = Rect {
+            left: Val::Px(stick.radius - knob_half_size + knob_offset.x),
+            bottom: Val::Px(stick.radius - knob_half_size + knob_offset.y),
+            ..Default::default()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn touch_pos_is_bottom_left_based() {
+        let pos = get_touch_screen_pos(Vec2::new(10., 30.), 100.);
+
+        if cfg!(any(target_os = "android", target_os = "ios")) {
+            assert_eq!(pos, Vec2::new(10., 30.));
+        } else {
+            assert_eq!(pos, Vec2::new(10., 70.));
+        }
+    }
+}
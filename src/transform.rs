@@ -1,9 +1,18 @@
-pub struct PlayerPlugin;
-impl Plugin for PlayerPlugin {
+use crate::{
+    fixed_step::{FixedStep, FixedSystem, FixedUpdateStage, Interpolated},
+    schedule::{PhaseSystem, UpdatePhase},
+};
+use bevy::prelude::*;
+use bevy_inspector_egui::Inspectable;
+
+pub struct TransformUtilsPlugin;
+impl Plugin for TransformUtilsPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.add_system_to_stage(CoreStage::PostUpdate, rotate)
-            .add_system_to_stage(CoreStage::Last, follow_scale)
-            .add_system_to_stage(CoreStage::Last, follow_position);
+        app.add_system(add_rotation_interpolation.in_phase(UpdatePhase::Logic))
+            .add_system_to_stage(FixedUpdateStage, rotate.in_fixed_step())
+            // followers pick up the final transforms of the frame
+            .add_system(follow_scale.in_phase(UpdatePhase::Animation))
+            .add_system(follow_position.in_phase(UpdatePhase::Animation));
     }
 }
 
@@ -43,17 +52,40 @@ pub struct FollowScale {
     scale_multiplier: Vec3,
 }
 
+impl FollowScale {
+    pub fn new(followed_e: Entity, scale_multiplier: Vec3) -> Self {
+        Self {
+            followed_e,
+            scale_multiplier,
+        }
+    }
+}
+
 #[derive(Component, Inspectable)]
 pub struct FollowPosition {
     followed_e: Entity,
     offset: Vec3,
 }
 
-fn rotate(mut q: Query<(&TransformRotation, &mut Transform)>, time: ScaledTime) {
+impl FollowPosition {
+    pub fn new(followed_e: Entity, offset: Vec3) -> Self {
+        Self { followed_e, offset }
+    }
+}
+
+/// Rotation is simulated in the fixed step, so it gets rendered interpolated
+fn add_rotation_interpolation(
+    mut commands: Commands,
+    q: Query<Entity, (Added<TransformRotation>, Without<Interpolated>)>,
+) {
+    for e in q.iter() {
+        commands.entity(e).insert(Interpolated::default());
+    }
+}
+
+fn rotate(mut q: Query<(&TransformRotation, &mut Transform)>, fixed: Res<FixedStep>) {
     for (r, mut t) in q.iter_mut() {
-        t.rotate(Quat::from_rotation_z(
-            r.rotation_rad * time.scaled_delta_seconds(),
-        ));
+        t.rotate(Quat::from_rotation_z(r.rotation_rad * fixed.get_step_sec()));
     }
 }
 
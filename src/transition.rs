@@ -0,0 +1,364 @@
+use crate::{
+    game_state::GameStateStack,
+    inactive::{Inactive, InactiveCommands, InactiveReason},
+    menu::UiFocus,
+    reset::Persistent,
+    schedule::{PhaseSystem, UpdatePhase},
+    settings::Settings,
+    GameState,
+};
+use bevy::{
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+    ui::FocusPolicy,
+};
+use bevy_time::*;
+
+pub struct TransitionPlugin;
+impl Plugin for TransitionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Transition>()
+            .add_event::<TransitionTo>()
+            .add_startup_system(create_iris_image)
+            .add_system(start_transition.in_phase(UpdatePhase::Logic))
+            .add_system(
+                update_transition
+                    .in_phase(UpdatePhase::Logic)
+                    .after(start_transition),
+            )
+            .add_system(
+                block_panel_input
+                    .in_phase(UpdatePhase::Logic)
+                    .after(update_transition),
+            )
+            // after the ui z and transforms are propagated, right before rendering
+            .add_system_to_stage(CoreStage::Last, raise_overlay);
+    }
+}
+
+pub const DEFAULT_TRANSITION_SEC: f32 = 0.6;
+const IRIS_IMAGE_SIZE: u32 = 128;
+/// Ui z is assigned by hierarchy and spawn order, so the overlay gets pushed over everything explicitly
+const OVERLAY_Z: f32 = 900.;
+
+/// Covers the screen, switches to the state at the midpoint and uncovers it again.
+/// The switch replaces the whole state stack, so overlays like the pause menu get closed as well.
+/// The duration (in seconds) is for the whole transition.
+/// Requests are ignored while another transition is running.
+pub struct TransitionTo(pub GameState, pub TransitionStyle, pub f32);
+
+impl TransitionTo {
+    pub fn fade(state: GameState) -> Self {
+        Self(state, TransitionStyle::Fade, DEFAULT_TRANSITION_SEC)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransitionStyle {
+    Fade,
+    /// Left to right
+    Wipe,
+    /// Circle closing on the center of the screen
+    Iris,
+}
+
+#[derive(Default)]
+pub struct Transition {
+    active: Option<ActiveTransition>,
+}
+
+impl Transition {
+    pub fn is_active(&self) -> bool {
+        self.active.is_some()
+    }
+}
+
+struct ActiveTransition {
+    state: GameState,
+    style: TransitionStyle,
+    duration_sec: f32,
+    elapsed_sec: f32,
+    is_switched: bool,
+    overlay_e: Entity,
+}
+
+impl ActiveTransition {
+    /// 0 when the screen is clear, 1 when it's fully covered
+    fn get_coverage(&self) -> f32 {
+        let half = self.duration_sec * 0.5;
+        let progress = if half > 0. {
+            (self.elapsed_sec / half).min(2.)
+        } else {
+            1.
+        };
+        let coverage = if progress <= 1. {
+            progress
+        } else {
+            2. - progress
+        };
+
+        // smoothstep
+        coverage * coverage * (3. - 2. * coverage)
+    }
+}
+
+struct IrisImage(Handle<Image>);
+
+#[derive(Component)]
+struct TransitionOverlay;
+
+#[derive(Component)]
+enum TransitionPart {
+    Fill,
+    IrisHole,
+    IrisBar(Vec2),
+}
+
+fn create_iris_image(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let size = IRIS_IMAGE_SIZE;
+    let radius = size as f32 * 0.5;
+    let mut data = Vec::with_capacity((size * size * 4) as usize);
+
+    for y in 0..size {
+        for x in 0..size {
+            let dist = Vec2::new(x as f32 + 0.5, y as f32 + 0.5).distance(Vec2::splat(radius));
+            // opaque outside of the circle with a 1px soft edge
+            let alpha = (dist - radius + 1.).clamp(0., 1.);
+            data.extend_from_slice(&[255, 255, 255, (alpha * 255.) as u8]);
+        }
+    }
+
+    let img = Image::new(
+        Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    );
+
+    commands.insert_resource(IrisImage(images.add(img)));
+}
+
+fn start_transition(
+    mut commands: Commands,
+    mut transition_evr: EventReader<TransitionTo>,
+    mut transition: ResMut<Transition>,
+    iris_img: Res<IrisImage>,
+    settings: Res<Settings>,
+) {
+    for TransitionTo(state, style, duration_sec) in transition_evr.iter() {
+        if transition.is_active() {
+            continue;
+        }
+
+        // wipes and irises are a lot of movement
+        let style = if settings.reduce_motion {
+            TransitionStyle::Fade
+        } else {
+            *style
+        };
+
+        transition.active = Some(ActiveTransition {
+            state: *state,
+            style,
+            duration_sec: *duration_sec,
+            elapsed_sec: 0.,
+            is_switched: false,
+            overlay_e: spawn_overlay(&mut commands, style, &iris_img),
+        });
+    }
+}
+
+fn spawn_overlay(commands: &mut Commands, style: TransitionStyle, iris_img: &IrisImage) -> Entity {
+    let get_part_bundle = || NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            ..Default::default()
+        },
+        color: Color::BLACK.into(),
+        // shown once the first update has sized it
+        visibility: Visibility { is_visible: false },
+        ..Default::default()
+    };
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                position_type: PositionType::Absolute,
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            // swallows clicks until the transition is done
+            focus_policy: FocusPolicy::Block,
+            ..Default::default()
+        })
+        .insert(TransitionOverlay)
+        // a reset during the transition mustn't take the overlay with it
+        .insert(Persistent)
+        .with_children(|b| match style {
+            TransitionStyle::Fade | TransitionStyle::Wipe => {
+                b.spawn_bundle(get_part_bundle())
+                    .insert(TransitionPart::Fill);
+            }
+            TransitionStyle::Iris => {
+                b.spawn_bundle(NodeBundle {
+                    image: iris_img.0.clone().into(),
+                    ..get_part_bundle()
+                })
+                .insert(TransitionPart::IrisHole);
+
+                for side in [Vec2::X, -Vec2::X, Vec2::Y, -Vec2::Y] {
+                    b.spawn_bundle(get_part_bundle())
+                        .insert(TransitionPart::IrisBar(side));
+                }
+            }
+        })
+        .id()
+}
+
+fn update_transition(
+    mut commands: Commands,
+    mut transition: ResMut<Transition>,
+    mut state: ResMut<State<GameState>>,
+    mut part_q: Query<(&TransitionPart, &mut Style, &mut UiColor, &mut Visibility)>,
+    wnds: Res<Windows>,
+    time: ScaledTime,
+) {
+    let active = match transition.active.as_mut() {
+        Some(active) => active,
+        None => return,
+    };
+
+    // real time, so transitions work while paused
+    active.elapsed_sec += time.delta().as_secs_f32();
+
+    if !active.is_switched && active.elapsed_sec >= active.duration_sec * 0.5 {
+        active.is_switched = true;
+
+        if let Err(err) = state.go_to(active.state) {
+            warn!("Transition failed: {err:?}");
+        }
+    }
+
+    if active.elapsed_sec >= active.duration_sec {
+        commands.entity(active.overlay_e).despawn_recursive();
+        transition.active = None;
+        return;
+    }
+
+    let coverage = active.get_coverage();
+    let window_size = wnds
+        .get_primary()
+        .map_or(Vec2::ONE, |wnd| Vec2::new(wnd.width(), wnd.height()));
+
+    for (part, mut style, mut color, mut visibility) in part_q.iter_mut() {
+        visibility.is_visible = true;
+
+        match (part, active.style) {
+            (TransitionPart::Fill, TransitionStyle::Fade) => {
+                style.size = Size::new(Val::Percent(100.), Val::Percent(100.));
+                color.0.set_a(coverage);
+            }
+            (TransitionPart::Fill, _) => {
+                // the uncovering continues to the right instead of going back
+                let left = if active.is_switched {
+                    1. - coverage
+                } else {
+                    0.
+                };
+                style.size = Size::new(Val::Percent(coverage * 100.), Val::Percent(100.));
+                style.position = Rect {
+                    left: Val::Percent(left * 100.),
+                    bottom: Val::Px(0.),
+                    ..Default::default()
+                };
+            }
+            (TransitionPart::IrisHole, _) => {
+                let (min, max) = get_iris_hole(window_size, coverage);
+                style.size = Size::new(Val::Px(max.x - min.x), Val::Px(max.y - min.y));
+                style.position = Rect {
+                    left: Val::Px(min.x),
+                    bottom: Val::Px(min.y),
+                    ..Default::default()
+                };
+            }
+            (TransitionPart::IrisBar(side), _) => {
+                // the side bars span the full height, top and bottom fill the gaps above and below the hole
+                let (min, max) = get_iris_hole(window_size, coverage);
+                let (pos, size) = if side.x < 0. {
+                    (Vec2::ZERO, Vec2::new(min.x, window_size.y))
+                } else if side.x > 0. {
+                    (
+                        Vec2::new(max.x, 0.),
+                        Vec2::new(window_size.x - max.x, window_size.y),
+                    )
+                } else if side.y < 0. {
+                    (Vec2::new(min.x, 0.), Vec2::new(max.x - min.x, min.y))
+                } else {
+                    (
+                        Vec2::new(min.x, max.y),
+                        Vec2::new(max.x - min.x, window_size.y - max.y),
+                    )
+                };
+
+                style.size = Size::new(Val::Px(size.x.max(0.)), Val::Px(size.y.max(0.)));
+                style.position = Rect {
+                    left: Val::Px(pos.x),
+                    bottom: Val::Px(pos.y),
+                    ..Default::default()
+                };
+            }
+        }
+    }
+}
+
+/// Clicks are blocked by the overlay, keyboard and gamepad navigation has to be blocked on the panels.
+/// Panels spawned by the state switch are caught as well.
+fn block_panel_input(
+    mut commands: Commands,
+    transition: Res<Transition>,
+    panel_q: Query<(Entity, Option<&Inactive>), With<UiFocus>>,
+) {
+    let is_active = transition.is_active();
+
+    for (panel_e, inactive) in panel_q.iter() {
+        let is_blocked = inactive.map_or(false, |i| i.has_reason(InactiveReason::Transition));
+
+        if is_active && !is_blocked {
+            commands.deactivate(panel_e, InactiveReason::Transition);
+        } else if !is_active && is_blocked {
+            commands.activate(panel_e, InactiveReason::Transition);
+        }
+    }
+}
+
+fn raise_overlay(
+    mut overlay_q: Query<(&mut GlobalTransform, &Children), With<TransitionOverlay>>,
+    mut part_q: Query<(&mut GlobalTransform, &Transform), Without<TransitionOverlay>>,
+) {
+    for (mut overlay_t, children) in overlay_q.iter_mut() {
+        overlay_t.translation.z = OVERLAY_Z;
+
+        for child_e in children.iter() {
+            if let Ok((mut part_t, local_t)) = part_q.get_mut(*child_e) {
+                part_t.translation.z = OVERLAY_Z + local_t.translation.z;
+            }
+        }
+    }
+}
+
+/// Bounds of the square containing the iris circle
+fn get_iris_hole(window_size: Vec2, coverage: f32) -> (Vec2, Vec2) {
+    // big enough to clear the corners when fully open
+    let half_size = window_size.length() * 0.5 * (1. - coverage);
+    let center = window_size * 0.5;
+
+    (
+        center - Vec2::splat(half_size),
+        center + Vec2::splat(half_size),
+    )
+}
@@ -0,0 +1,381 @@
+// in-game tutorial steps, progress is saved so finished steps don't show up again
+
+use crate::{
+    assets::Fonts,
+    game_state::GameStateHook,
+    input_binding::{BindingTarget, PlayerAction, PlayerInput, UiAction},
+    palette::{Palette, PaletteColor},
+    schedule::{PhaseSystem, UpdatePhase},
+    settings::Settings,
+    storage, GameState,
+};
+use bevy::{prelude::*, ui::FocusPolicy};
+use bevy_time::*;
+use dyn_fmt::AsStrFormatExt;
+use serde::{Deserialize, Serialize};
+
+pub struct TutorialPlugin;
+impl Plugin for TutorialPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(load_progress())
+            .add_event::<TutorialTriggerEvt>()
+            .add_event::<TutorialStepCompletedEvt>()
+            .add_system_set(
+                SystemSet::on_enter(GameState::Game)
+                    .with_system(spawn_prompt.in_phase(UpdatePhase::Logic)),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Game)
+                    .with_system(despawn_prompt.in_phase(UpdatePhase::Logic)),
+            )
+            .add_system(check_tutorial_step.in_phase(UpdatePhase::Logic))
+            .add_system(update_prompt.in_phase(UpdatePhase::Animation))
+            .add_system(update_highlights.in_phase(UpdatePhase::Animation));
+    }
+}
+
+const TUTORIAL_KEY: &str = "tutorial";
+/// Sent by the resume button, leaving the pause menu through restart or quit doesn't count
+pub const RESUME_TRIGGER: &str = "resume";
+const HALO_PADDING: f32 = 8.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TutorialStepId {
+    Pause,
+    Resume,
+    Reset,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepCondition {
+    /// Any player presses the action
+    Action(PlayerAction),
+    Hook(GameStateHook),
+    /// Sent by gameplay code through [`TutorialTriggerEvt`]
+    Trigger(&'static str),
+}
+
+pub struct TutorialStep {
+    pub id: TutorialStepId,
+    /// `{}` gets replaced by the first binding of the target
+    pub prompt: &'static str,
+    pub binding: Option<BindingTarget>,
+    pub condition: StepCondition,
+}
+
+/// Shown in order, entities with a matching [`TutorialTarget`] get highlighted while their step is shown
+pub const TUTORIAL_STEPS: [TutorialStep; 3] = [
+    TutorialStep {
+        id: TutorialStepId::Pause,
+        prompt: "Press {} to pause",
+        binding: Some(BindingTarget::Ui(UiAction::Cancel)),
+        condition: StepCondition::Hook(GameStateHook::Enter(GameState::Paused)),
+    },
+    TutorialStep {
+        id: TutorialStepId::Resume,
+        prompt: "Pick resume to get back to the game",
+        binding: None,
+        condition: StepCondition::Trigger(RESUME_TRIGGER),
+    },
+    TutorialStep {
+        id: TutorialStepId::Reset,
+        prompt: "Press {} to restart the round",
+        binding: Some(BindingTarget::Player(PlayerAction::Reset)),
+        condition: StepCondition::Action(PlayerAction::Reset),
+    },
+];
+
+pub struct TutorialTriggerEvt(pub &'static str);
+
+pub struct TutorialStepCompletedEvt(pub TutorialStepId);
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TutorialProgress {
+    pub completed: Vec<TutorialStepId>,
+}
+
+impl TutorialProgress {
+    pub fn get_current_step(&self) -> Option<&'static TutorialStep> {
+        TUTORIAL_STEPS
+            .iter()
+            .find(|step| !self.completed.contains(&step.id))
+    }
+}
+
+/// World sprites or ui nodes to highlight during the step
+#[derive(Component)]
+pub struct TutorialTarget(pub TutorialStepId);
+
+/// The halo entity of a highlighted target
+#[derive(Component)]
+struct Highlighted(Entity);
+
+#[derive(Component)]
+struct TutorialHalo;
+
+#[derive(Component)]
+struct TutorialPrompt;
+
+fn load_progress() -> TutorialProgress {
+    storage::load(TUTORIAL_KEY)
+        .and_then(|data| match ron::from_str(&data) {
+            Ok(progress) => Some(progress),
+            Err(err) => {
+                warn!("Failed to load tutorial progress: {err}");
+                None
+            }
+        })
+        .unwrap_or_default()
+}
+
+fn save_progress(progress: &TutorialProgress) {
+    match ron::to_string(progress) {
+        Ok(data) => {
+            if let Err(err) = storage::save(TUTORIAL_KEY, &data) {
+                warn!("Failed to save tutorial progress: {err}");
+            }
+        }
+        Err(err) => warn!("Failed to serialize tutorial progress: {err}"),
+    }
+}
+
+fn get_prompt_text(step: &TutorialStep, settings: &Settings) -> String {
+    match step.binding {
+        Some(target) => {
+            let binding_name = settings
+                .bindings
+                .get(target)
+                .first()
+                .map_or_else(|| target.get_name(), |b| b.get_name());
+            step.prompt.format(&[binding_name])
+        }
+        None => step.prompt.to_string(),
+    }
+}
+
+fn spawn_prompt(
+    mut commands: Commands,
+    fonts: Res<Fonts>,
+    palette: Res<Palette>,
+    progress: Res<TutorialProgress>,
+) {
+    if progress.get_current_step().is_none() {
+        return;
+    }
+
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(30.),
+                    left: Val::Px(30.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: fonts.ui.clone(),
+                    font_size: 30.,
+                    color: palette.get_color(&PaletteColor::Text),
+                },
+                Default::default(),
+            ),
+            focus_policy: FocusPolicy::Pass,
+            ..Default::default()
+        })
+        .insert(PaletteColor::Text)
+        .insert(TutorialPrompt);
+}
+
+fn despawn_prompt(mut commands: Commands, prompt_q: Query<Entity, With<TutorialPrompt>>) {
+    for e in prompt_q.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
+
+fn check_tutorial_step(
+    mut progress: ResMut<TutorialProgress>,
+    mut hook_evr: EventReader<GameStateHook>,
+    mut trigger_evr: EventReader<TutorialTriggerEvt>,
+    mut completed_evw: EventWriter<TutorialStepCompletedEvt>,
+    input_q: Query<&PlayerInput>,
+) {
+    let hooks: Vec<_> = hook_evr.iter().copied().collect();
+    let triggers: Vec<_> = trigger_evr.iter().map(|ev| ev.0).collect();
+
+    let step = match progress.get_current_step() {
+        Some(step) => step,
+        None => return,
+    };
+
+    let is_done = match step.condition {
+        StepCondition::Action(action) => input_q.iter().any(|input| input.just_pressed(action)),
+        StepCondition::Hook(hook) => hooks.contains(&hook),
+        StepCondition::Trigger(name) => triggers.contains(&name),
+    };
+
+    if is_done {
+        progress.completed.push(step.id);
+        save_progress(&progress);
+        completed_evw.send(TutorialStepCompletedEvt(step.id));
+    }
+}
+
+fn update_prompt(
+    mut commands: Commands,
+    progress: Res<TutorialProgress>,
+    settings: Res<Settings>,
+    mut prompt_q: Query<(Entity, &mut Text), With<TutorialPrompt>>,
+) {
+    if !progress.is_changed() && !settings.is_changed() {
+        // a freshly spawned prompt has no text yet
+        if prompt_q
+            .iter()
+            .all(|(_, text)| !text.sections[0].value.is_empty())
+        {
+            return;
+        }
+    }
+
+    for (prompt_e, mut text) in prompt_q.iter_mut() {
+        match progress.get_current_step() {
+            Some(step) => text.sections[0].value = get_prompt_text(step, &settings),
+            None => commands.entity(prompt_e).despawn_recursive(),
+        }
+    }
+}
+
+fn update_highlights(
+    mut commands: Commands,
+    progress: Res<TutorialProgress>,
+    target_q: Query<(
+        Entity,
+        &TutorialTarget,
+        Option<&Highlighted>,
+        Option<&Node>,
+        Option<&Sprite>,
+    )>,
+    mut halo_q: Query<(Option<&mut UiColor>, Option<&mut Sprite>), With<TutorialHalo>>,
+    palette: Res<Palette>,
+    settings: Res<Settings>,
+    time: ScaledTime,
+    mut elapsed_sec: Local<f32>,
+) {
+    let current_id = progress.get_current_step().map(|step| step.id);
+
+    for (target_e, target, highlighted, node, sprite) in target_q.iter() {
+        let is_current = current_id == Some(target.0);
+
+        match highlighted {
+            Some(Highlighted(halo_e)) if !is_current => {
+                commands.entity(*halo_e).despawn_recursive();
+                commands.entity(target_e).remove::<Highlighted>();
+            }
+            None if is_current => {
+                let halo_e = spawn_halo(&mut commands, target_e, node, sprite);
+                commands.entity(target_e).insert(Highlighted(halo_e));
+            }
+            _ => {}
+        }
+    }
+
+    // real time, so the ui highlights pulse in the pause menu too
+    *elapsed_sec += time.delta().as_secs_f32();
+    let alpha = if settings.reduce_motion {
+        0.35
+    } else {
+        0.25 + (*elapsed_sec * 5.).sin() * 0.15
+    };
+    let mut color = palette.get_color(&PaletteColor::ButtonAccent);
+    color.set_a(alpha);
+
+    for (ui_color, sprite) in halo_q.iter_mut() {
+        if let Some(mut ui_color) = ui_color {
+            ui_color.0 = color;
+        }
+
+        if let Some(mut sprite) = sprite {
+            sprite.color = color;
+        }
+    }
+}
+
+fn spawn_halo(
+    commands: &mut Commands,
+    target_e: Entity,
+    node: Option<&Node>,
+    sprite: Option<&Sprite>,
+) -> Entity {
+    let mut halo = if node.is_some() {
+        // ui children are drawn over their parent, hence the low alpha
+        commands.spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect::all(Val::Px(-HALO_PADDING)),
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            focus_policy: FocusPolicy::Pass,
+            ..Default::default()
+        })
+    } else {
+        let size = sprite.and_then(|s| s.custom_size).unwrap_or(Vec2::ONE);
+        commands.spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: Color::NONE,
+                custom_size: Some(size + Vec2::splat(HALO_PADDING * 2.)),
+                ..Default::default()
+            },
+            // behind the target
+            transform: Transform::from_xyz(0., 0., -0.01),
+            ..Default::default()
+        })
+    };
+
+    let halo_e = halo.insert(TutorialHalo).id();
+    commands.entity(target_e).push_children(&[halo_e]);
+    halo_e
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn completed_steps_are_skipped() {
+        let mut progress = TutorialProgress::default();
+        assert_eq!(
+            progress.get_current_step().map(|s| s.id),
+            Some(TutorialStepId::Pause)
+        );
+
+        progress.completed.push(TutorialStepId::Pause);
+        progress.completed.push(TutorialStepId::Reset);
+        assert_eq!(
+            progress.get_current_step().map(|s| s.id),
+            Some(TutorialStepId::Resume)
+        );
+
+        progress.completed.push(TutorialStepId::Resume);
+        assert!(progress.get_current_step().is_none());
+    }
+
+    #[test]
+    fn progress_roundtrip() {
+        let progress = TutorialProgress {
+            completed: vec![TutorialStepId::Pause, TutorialStepId::Resume],
+        };
+        let data = ron::to_string(&progress).unwrap();
+
+        assert_eq!(ron::from_str::<TutorialProgress>(&data).unwrap(), progress);
+        // unknown or missing fields fall back to the defaults
+        assert_eq!(
+            ron::from_str::<TutorialProgress>("()").unwrap(),
+            TutorialProgress::default()
+        );
+    }
+}
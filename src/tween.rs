@@ -1,3 +1,4 @@
+use crate::schedule::{PhaseSystem, UpdatePhase};
 use bevy::prelude::*;
 use bevy_tweening::lens::{SpriteColorLens, TransformScaleLens};
 use bevy_tweening::*;
@@ -6,7 +7,7 @@ use std::time::Duration;
 pub struct TweenPlugin;
 impl Plugin for TweenPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.add_system(on_tween_completed);
+        app.add_system(on_tween_completed.in_phase(UpdatePhase::Animation));
     }
 }
 
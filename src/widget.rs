@@ -0,0 +1,300 @@
+use crate::{
+    inactive::Active,
+    input_binding::{UiAction, UiInput, UiNavInput},
+    menu::{ButtonTextEntity, UiFocus},
+    schedule::{PhaseSystem, UpdatePhase},
+    settings::SettingKind,
+};
+use bevy::{prelude::*, ui::FocusPolicy};
+
+pub struct WidgetPlugin;
+impl Plugin for WidgetPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SliderChangedEvt>()
+            .add_event::<ToggleChangedEvt>()
+            .add_event::<SelectorChangedEvt>()
+            .add_system(handle_widget_input.in_phase(UpdatePhase::Logic))
+            .add_system(handle_slider_drag.in_phase(UpdatePhase::Logic))
+            .add_system(handle_widget_click.in_phase(UpdatePhase::Logic))
+            .add_system(update_widget_text.in_phase(UpdatePhase::Animation))
+            .add_system(update_slider_fill.in_phase(UpdatePhase::Animation));
+    }
+}
+
+/// What a widget value is bound to, the widget counterpart of a `ButtonAction`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WidgetTarget {
+    Setting(SettingKind),
+}
+
+#[derive(Component, Clone, Copy)]
+pub struct Slider {
+    pub value: f32,
+    pub min: f32,
+    pub max: f32,
+    pub step: f32,
+    pub target: WidgetTarget,
+}
+
+#[derive(Component, Clone, Copy)]
+pub struct Toggle {
+    pub on: bool,
+    pub target: WidgetTarget,
+}
+
+#[derive(Component, Clone, Copy)]
+pub struct Selector {
+    pub index: usize,
+    pub options: &'static [&'static str],
+    pub target: WidgetTarget,
+}
+
+#[derive(Component)]
+pub struct WidgetLabel(pub String);
+
+/// Widgets which use left/right to change their value instead of moving the focus
+pub type HorizontalInputWidget = Or<(With<Slider>, With<Selector>)>;
+
+#[derive(Component)]
+pub struct SliderFill;
+
+pub struct SliderChangedEvt {
+    pub widget_e: Entity,
+    pub target: WidgetTarget,
+    pub value: f32,
+}
+
+pub struct ToggleChangedEvt {
+    pub widget_e: Entity,
+    pub target: WidgetTarget,
+    pub on: bool,
+}
+
+pub struct SelectorChangedEvt {
+    pub widget_e: Entity,
+    pub target: WidgetTarget,
+    pub index: usize,
+}
+
+impl Slider {
+    pub fn get_ratio(&self) -> f32 {
+        if self.max > self.min {
+            ((self.value - self.min) / (self.max - self.min)).clamp(0., 1.)
+        } else {
+            0.
+        }
+    }
+
+    pub fn get_text(&self, label: &str) -> String {
+        format!("{label}: {}%", (self.get_ratio() * 100.).round())
+    }
+
+    /// Snaps the value to the step and clamps it, returns whether the value changed
+    fn set_value(&mut self, value: f32) -> bool {
+        let mut value = value.clamp(self.min, self.max);
+
+        if self.step > 0. {
+            value = self.min + ((value - self.min) / self.step).round() * self.step;
+            value = value.clamp(self.min, self.max);
+        }
+
+        if (value - self.value).abs() > f32::EPSILON {
+            self.value = value;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl Toggle {
+    pub fn get_text(&self, label: &str) -> String {
+        format!("{label}: {}", if self.on { "on" } else { "off" })
+    }
+}
+
+impl Selector {
+    pub fn get_text(&self, label: &str) -> String {
+        format!("< {label}: {} >", self.options[self.index])
+    }
+
+    fn offset(&mut self, offset: isize) {
+        self.index =
+            (self.index as isize + offset).rem_euclid(self.options.len() as isize) as usize;
+    }
+}
+
+pub fn get_slider_fill_bundle(slider: &Slider, color: Color) -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            size: Size::new(Val::Percent(slider.get_ratio() * 100.), Val::Percent(15.)),
+            position_type: PositionType::Absolute,
+            position: Rect {
+                left: Val::Px(0.),
+                bottom: Val::Px(0.),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        color: color.into(),
+        focus_policy: FocusPolicy::Pass,
+        ..Default::default()
+    }
+}
+
+fn handle_widget_input(
+    panel_q: Query<(&UiInput, &UiNavInput, &UiFocus), Active>,
+    mut slider_q: Query<&mut Slider, Active>,
+    mut toggle_q: Query<&mut Toggle, Active>,
+    mut selector_q: Query<&mut Selector, Active>,
+    mut slider_evw: EventWriter<SliderChangedEvt>,
+    mut toggle_evw: EventWriter<ToggleChangedEvt>,
+    mut selector_evw: EventWriter<SelectorChangedEvt>,
+) {
+    for (input, nav, focus) in panel_q.iter() {
+        let widget_e = match focus.get_focused_entity() {
+            Some(e) => e,
+            None => continue,
+        };
+
+        let mut dir = 0;
+        if nav.just_fired(UiAction::Left) {
+            dir -= 1;
+        }
+        if nav.just_fired(UiAction::Right) {
+            dir += 1;
+        }
+
+        if let Ok(mut slider) = slider_q.get_mut(widget_e) {
+            if dir != 0 {
+                let value = slider.value + slider.step * dir as f32;
+                if slider.set_value(value) {
+                    slider_evw.send(SliderChangedEvt {
+                        widget_e,
+                        target: slider.target,
+                        value: slider.value,
+                    });
+                }
+            }
+        } else if let Ok(mut toggle) = toggle_q.get_mut(widget_e) {
+            if input.just_pressed(UiAction::Confirm) {
+                toggle.on = !toggle.on;
+                toggle_evw.send(ToggleChangedEvt {
+                    widget_e,
+                    target: toggle.target,
+                    on: toggle.on,
+                });
+            }
+        } else if let Ok(mut selector) = selector_q.get_mut(widget_e) {
+            if input.just_pressed(UiAction::Confirm) {
+                dir = 1;
+            }
+
+            if dir != 0 {
+                selector.offset(dir);
+                selector_evw.send(SelectorChangedEvt {
+                    widget_e,
+                    target: selector.target,
+                    index: selector.index,
+                });
+            }
+        }
+    }
+}
+
+fn handle_slider_drag(
+    windows: Res<Windows>,
+    mut slider_q: Query<(Entity, &Interaction, &Node, &GlobalTransform, &mut Slider), Active>,
+    mut slider_evw: EventWriter<SliderChangedEvt>,
+) {
+    let cursor_pos = match windows.get_primary().and_then(|w| w.cursor_position()) {
+        Some(pos) => pos,
+        None => return,
+    };
+
+    // ui interaction stays clicked until the mouse button is released, so this covers dragging as well
+    for (widget_e, interaction, node, t, mut slider) in slider_q.iter_mut() {
+        if matches!(interaction, Interaction::Clicked) && node.size.x > 0. {
+            let left = t.translation.x - node.size.x / 2.;
+            let ratio = ((cursor_pos.x - left) / node.size.x).clamp(0., 1.);
+            let value = slider.min + (slider.max - slider.min) * ratio;
+
+            if slider.set_value(value) {
+                slider_evw.send(SliderChangedEvt {
+                    widget_e,
+                    target: slider.target,
+                    value: slider.value,
+                });
+            }
+        }
+    }
+}
+
+fn handle_widget_click(
+    mut toggle_q: Query<(Entity, &Interaction, &mut Toggle), (Changed<Interaction>, Active)>,
+    mut selector_q: Query<(Entity, &Interaction, &mut Selector), (Changed<Interaction>, Active)>,
+    mut toggle_evw: EventWriter<ToggleChangedEvt>,
+    mut selector_evw: EventWriter<SelectorChangedEvt>,
+) {
+    for (widget_e, interaction, mut toggle) in toggle_q.iter_mut() {
+        if matches!(interaction, Interaction::Clicked) {
+            toggle.on = !toggle.on;
+            toggle_evw.send(ToggleChangedEvt {
+                widget_e,
+                target: toggle.target,
+                on: toggle.on,
+            });
+        }
+    }
+
+    for (widget_e, interaction, mut selector) in selector_q.iter_mut() {
+        if matches!(interaction, Interaction::Clicked) {
+            selector.offset(1);
+            selector_evw.send(SelectorChangedEvt {
+                widget_e,
+                target: selector.target,
+                index: selector.index,
+            });
+        }
+    }
+}
+
+fn update_widget_text(
+    slider_q: Query<(&Slider, &WidgetLabel, &ButtonTextEntity), Changed<Slider>>,
+    toggle_q: Query<(&Toggle, &WidgetLabel, &ButtonTextEntity), Changed<Toggle>>,
+    selector_q: Query<(&Selector, &WidgetLabel, &ButtonTextEntity), Changed<Selector>>,
+    mut text_q: Query<&mut Text>,
+) {
+    let texts = slider_q
+        .iter()
+        .map(|(slider, label, txt_e)| (slider.get_text(&label.0), txt_e))
+        .chain(
+            toggle_q
+                .iter()
+                .map(|(toggle, label, txt_e)| (toggle.get_text(&label.0), txt_e)),
+        )
+        .chain(
+            selector_q
+                .iter()
+                .map(|(selector, label, txt_e)| (selector.get_text(&label.0), txt_e)),
+        );
+
+    for (value, txt_e) in texts {
+        if let Ok(mut text) = text_q.get_mut(txt_e.0) {
+            text.sections[0].value = value.to_uppercase();
+        }
+    }
+}
+
+fn update_slider_fill(
+    slider_q: Query<(&Slider, &Children), Changed<Slider>>,
+    mut fill_q: Query<&mut Style, With<SliderFill>>,
+) {
+    for (slider, children) in slider_q.iter() {
+        for child in children.iter() {
+            if let Ok(mut style) = fill_q.get_mut(*child) {
+                style.size.width = Val::Percent(slider.get_ratio() * 100.);
+            }
+        }
+    }
+}